@@ -0,0 +1,142 @@
+use rand::rngs::StdRng;
+
+use crate::{components::{Enemy, Player}, dice::Dice, world::{Entity, World}};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HitKind {
+    Miss,
+    Hit,
+    // A natural 20: the damage dice are rolled twice and armour is ignored.
+    Critical
+}
+
+// Everything one attack did, sent as an event for the log.
+pub struct AttackOutcome {
+    pub attacker: Entity,
+    pub defender: Entity,
+    pub kind: HitKind,
+    pub damage: usize,
+    // How much of the damage the defender's armour soaked up.
+    pub absorbed: usize
+}
+
+// The attacker's side of an attack.
+pub struct AttackerStats {
+    pub strength: usize,
+    pub damage: Dice
+}
+
+// The defender's side of an attack.
+pub struct DefenderStats {
+    pub defense: usize,
+    pub armor: usize
+}
+
+const D20: Dice = Dice { count: 1, sides: 20, bonus: 0 };
+
+// What an attack roll has to reach against a defender without `Defense`.
+pub const DEFAULT_DEFENSE: usize = 10;
+
+// Rolls a d20 plus strength against the defender's defense. A natural 1 always
+// misses and a natural 20 always lands as a critical. A hit always does at
+// least 1 damage, however thick the armour.
+pub fn resolve(attacker: Entity, stats: &AttackerStats, defender: Entity, against: &DefenderStats, rng: &mut StdRng) -> AttackOutcome {
+    let roll = D20.roll(rng);
+    resolve_roll(roll, attacker, stats, defender, against, rng)
+}
+
+// `resolve` once the d20 has come up `roll`; the rng only rolls damage.
+fn resolve_roll(roll: usize, attacker: Entity, stats: &AttackerStats, defender: Entity, against: &DefenderStats, rng: &mut StdRng) -> AttackOutcome {
+    let kind = match roll {
+        1 => HitKind::Miss,
+        20 => HitKind::Critical,
+        roll if roll + stats.strength >= against.defense => HitKind::Hit,
+        _ => HitKind::Miss
+    };
+    let (damage, absorbed) = match kind {
+        HitKind::Miss => (0, 0),
+        HitKind::Hit => {
+            let rolled = stats.damage.roll(rng).max(1);
+            let absorbed = against.armor.min(rolled - 1);
+            (rolled - absorbed, absorbed)
+        },
+        HitKind::Critical => {
+            let rolled = stats.damage.roll(rng) + stats.damage.roll(rng);
+            (rolled.max(1), 0)
+        }
+    };
+    AttackOutcome { attacker, defender, kind, damage, absorbed }
+}
+
+// The player and the monsters are at war; monsters leave each other alone.
+pub fn are_hostile(world: &World, a: Entity, b: Entity) -> bool {
+    (world.has::<Player>(a) && world.has::<Enemy>(b)) || (world.has::<Enemy>(a) && world.has::<Player>(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::map::Map;
+
+    use super::*;
+
+    fn entities() -> (Entity, Entity) {
+        let mut world = World::new(Map::new(4, 4));
+        (world.spawn((Player,)), world.spawn((Enemy,)))
+    }
+
+    fn attacker(strength: usize, damage: &str) -> AttackerStats {
+        AttackerStats { strength, damage: damage.parse().unwrap() }
+    }
+
+    #[test]
+    fn natural_1_always_misses() {
+        let (a, b) = entities();
+        let mut rng = StdRng::seed_from_u64(0);
+        let outcome = resolve_roll(1, a, &attacker(100, "1d6"), b, &DefenderStats { defense: 0, armor: 0 }, &mut rng);
+        assert_eq!(outcome.kind, HitKind::Miss);
+        assert_eq!((outcome.damage, outcome.absorbed), (0, 0));
+    }
+
+    #[test]
+    fn natural_20_is_a_critical_that_ignores_armour() {
+        let (a, b) = entities();
+        let mut rng = StdRng::seed_from_u64(0);
+        let against = DefenderStats { defense: 100, armor: 100 };
+        let outcome = resolve_roll(20, a, &attacker(0, "3"), b, &against, &mut rng);
+        assert_eq!(outcome.kind, HitKind::Critical);
+        assert_eq!((outcome.damage, outcome.absorbed), (6, 0));
+    }
+
+    #[test]
+    fn hits_on_reaching_defense() {
+        let (a, b) = entities();
+        let mut rng = StdRng::seed_from_u64(0);
+        let against = DefenderStats { defense: 12, armor: 0 };
+        assert_eq!(resolve_roll(9, a, &attacker(3, "1"), b, &against, &mut rng).kind, HitKind::Hit);
+        assert_eq!(resolve_roll(8, a, &attacker(3, "1"), b, &against, &mut rng).kind, HitKind::Miss);
+    }
+
+    #[test]
+    fn armour_leaves_at_least_1_damage() {
+        let (a, b) = entities();
+        let mut rng = StdRng::seed_from_u64(0);
+        let against = DefenderStats { defense: 0, armor: 10 };
+        let outcome = resolve_roll(10, a, &attacker(0, "4"), b, &against, &mut rng);
+        assert_eq!((outcome.kind, outcome.damage, outcome.absorbed), (HitKind::Hit, 1, 3));
+        let outcome = resolve_roll(10, a, &attacker(0, "1d4-4"), b, &against, &mut rng);
+        assert_eq!((outcome.damage, outcome.absorbed), (1, 0));
+    }
+
+    #[test]
+    fn every_hit_does_damage() {
+        let (a, b) = entities();
+        let mut rng = StdRng::seed_from_u64(7);
+        let against = DefenderStats { defense: 15, armor: 3 };
+        for _ in 0..1000 {
+            let outcome = resolve(a, &attacker(2, "1d3-1"), b, &against, &mut rng);
+            assert_eq!(outcome.kind == HitKind::Miss, outcome.damage == 0);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::world::Entity;
+use crate::dice::Dice;
 
 #[derive(PartialEq, Clone)]
 pub struct Position {
@@ -11,10 +11,73 @@ impl Position {
     }
 }
 
+pub struct Player;
+
+pub struct Enemy;
+
+// Nothing else that blocks can stand on the same tile.
+pub struct BlocksTile;
+
+// Which entry of the monster raws an enemy was spawned from.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MonsterKind(pub usize);
+
 pub struct HP(pub usize);
 
+pub struct MaxHP(pub usize);
+
+// Added to every attack roll.
 pub struct Strength(pub usize);
 
-pub struct AggressionIntent(pub Entity);
+// Rolled for the damage of each hit.
+pub struct Damage(pub Dice);
+
+// What an attack roll has to reach to hit.
+pub struct Defense(pub usize);
+
+// Taken off the damage of every hit that isn't critical.
+pub struct Armor(pub usize);
+
+pub struct Speed(pub usize);
+
+pub struct Energy(pub usize);
+impl Energy {
+    pub const THRESHOLD: usize = 100;
+
+    pub fn is_ready(&self) -> bool {
+        self.0 >= Self::THRESHOLD
+    }
+}
+
+pub struct Gold(pub usize);
+
+// Gold lying on the floor, picked up by walking over it.
+pub struct Treasure(pub usize);
+
+pub struct MovementIntent(pub Position);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AIState {
+    Idle,
+    Wandering,
+    Hunting,
+    Fleeing
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Behaviour {
+    // Chases the player and runs once badly hurt.
+    Hunter,
+    // Chases the player and never runs.
+    Berserker,
+    // Runs from the player on sight.
+    Coward
+}
 
-pub struct Damage(pub usize);
\ No newline at end of file
+#[derive(Clone, Copy)]
+pub struct AI {
+    pub state: AIState,
+    pub sight_radius: usize,
+    pub behaviour: Behaviour,
+    pub stationary: bool
+}
\ No newline at end of file
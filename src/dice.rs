@@ -0,0 +1,114 @@
+use std::{fmt::Display, str::FromStr};
+
+use rand::{rngs::StdRng, Rng};
+
+// Dice notation such as `2d6+1`, `d20` or a flat `3`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dice {
+    pub count: usize,
+    pub sides: usize,
+    pub bonus: isize
+}
+impl Dice {
+    // Rolls never go below zero, whatever the bonus.
+    pub fn roll(&self, rng: &mut StdRng) -> usize {
+        let total: usize = (0..self.count).map(|_| rng.random_range(1..=self.sides)).sum();
+        total.saturating_add_signed(self.bonus)
+    }
+
+    pub fn min(&self) -> usize {
+        self.count.saturating_add_signed(self.bonus)
+    }
+}
+impl FromStr for Dice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid dice `{s}`, expected something like `2d6+1`");
+        let text: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let (dice, bonus) = match text.find(['+', '-']) {
+            Some(idx) => {
+                // `parse` would take a second sign, turning `1d6--2` into `1d6+2`.
+                if text[idx + 1..].starts_with(['+', '-']) {
+                    return Err(invalid());
+                }
+                let bonus: isize = text[idx + 1..].parse().map_err(|_| invalid())?;
+                (&text[..idx], if text[idx..].starts_with('-') { -bonus } else { bonus })
+            },
+            None => (text.as_str(), 0)
+        };
+        let Some((count, sides)) = dice.split_once('d') else {
+            let flat: isize = dice.parse().map_err(|_| invalid())?;
+            return Ok(Self { count: 0, sides: 0, bonus: flat + bonus });
+        };
+        let count = if count.is_empty() { 1 } else { count.parse().map_err(|_| invalid())? };
+        let sides: usize = sides.parse().map_err(|_| invalid())?;
+        if count == 0 {
+            return Err(format!("invalid dice `{s}`, roll at least one die or use a flat number"));
+        }
+        if sides == 0 {
+            return Err(format!("invalid dice `{s}`, dice need at least one side"));
+        }
+        Ok(Self { count, sides, bonus })
+    }
+}
+impl Display for Dice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.count, self.bonus) {
+            (0, bonus) => write!(f, "{bonus}"),
+            (count, 0) => write!(f, "{count}d{}", self.sides),
+            (count, bonus) => write!(f, "{count}d{}{bonus:+}", self.sides)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn dice(count: usize, sides: usize, bonus: isize) -> Dice {
+        Dice { count, sides, bonus }
+    }
+
+    #[test]
+    fn parses_notation() {
+        assert_eq!("d20".parse(), Ok(dice(1, 20, 0)));
+        assert_eq!("2d6+1".parse(), Ok(dice(2, 6, 1)));
+        assert_eq!("1d4-1".parse(), Ok(dice(1, 4, -1)));
+        assert_eq!(" 3d8 + 2 ".parse(), Ok(dice(3, 8, 2)));
+        assert_eq!("3".parse(), Ok(dice(0, 0, 3)));
+    }
+
+    #[test]
+    fn rejects_dice_that_cannot_roll() {
+        assert!("0d3".parse::<Dice>().is_err());
+        assert!("1d0".parse::<Dice>().is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for text in ["", "d", "2d", "2x6", "d6+", "1d6+x", "-d6", "1.5d6", "1d6--2", "1d6+-2"] {
+            assert!(text.parse::<Dice>().is_err(), "`{text}` parsed");
+        }
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for text in ["1d20", "2d6+1", "1d4-1", "3"] {
+            assert_eq!(text.parse::<Dice>().unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn rolls_stay_in_range() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let dice = dice(2, 6, 1);
+        for _ in 0..1000 {
+            assert!((3..=13).contains(&dice.roll(&mut rng)));
+        }
+        assert_eq!(dice.min(), 3);
+        assert_eq!("1d4-5".parse::<Dice>().unwrap().roll(&mut rng), 0);
+    }
+}
@@ -0,0 +1,131 @@
+use std::mem;
+
+use rand::rngs::StdRng;
+
+use crate::{components::{BlocksTile, Player, Position}, game::GameConfig, map::Map, mapgen::{self, GeneratorKind, Layout}, pathfinding::distance_map, query::{With, Without}, storage::DetachedRow, tile::Tile, world::{Entity, World}};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StairsDirection {
+    Up,
+    Down
+}
+impl StairsDirection {
+    pub fn tile(self) -> Tile {
+        match self {
+            StairsDirection::Up => Tile::StairsUp,
+            StairsDirection::Down => Tile::StairsDown
+        }
+    }
+
+    fn opposite(self) -> Self {
+        match self {
+            StairsDirection::Up => StairsDirection::Down,
+            StairsDirection::Down => StairsDirection::Up
+        }
+    }
+}
+
+// A level that is not loaded into the world, kept exactly as the player left it.
+struct Level {
+    map: Map,
+    entities: Vec<DetachedRow>
+}
+
+// The stack of levels, indexed by depth. The current level lives in the world
+// itself, so its slot stays empty until the player leaves it.
+pub struct Dungeon {
+    levels: Vec<Option<Level>>,
+    depth: usize
+}
+impl Default for Dungeon {
+    fn default() -> Self {
+        Self { levels: vec![None], depth: 0 }
+    }
+}
+impl Dungeon {
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    // Stashes the current level and loads the one above or below, generating it on
+    // the first visit. Returns the new depth, or `None` if there is nowhere to go.
+    pub fn change_level(world: &mut World, direction: StairsDirection) -> Option<usize> {
+        let depth = world.resource::<Dungeon>().depth;
+        let target = match direction {
+            StairsDirection::Up => depth.checked_sub(1)?,
+            StairsDirection::Down => depth + 1
+        };
+        let stored = world.resource_mut::<Dungeon>().levels
+            .get_mut(target)
+            .and_then(Option::take);
+        let (map, entities, layout) = match stored {
+            Some(level) => (level.map, level.entities, None),
+            None => {
+                let (map, layout, rng) = Self::generate_map(world, target);
+                (map, vec![], Some((layout, rng)))
+            }
+        };
+
+        let others: Vec<Entity> = world.query_ref::<(), Without<Player>>()
+            .map(|(entity, _)| entity)
+            .collect();
+        let stashed = Level {
+            map: mem::replace(&mut world.map, map),
+            entities: others.into_iter().filter_map(|entity| world.detach(entity)).collect()
+        };
+        let dungeon = world.resource_mut::<Dungeon>();
+        if dungeon.levels.len() <= target {
+            dungeon.levels.resize_with(target + 1, || None);
+        }
+        dungeon.levels[depth] = Some(stashed);
+        dungeon.depth = target;
+
+        for entity in entities {
+            world.attach(entity);
+        }
+        // Arrive on the stairs leading back the way the player came.
+        let (x, y) = world.map.find_tile(direction.opposite().tile())
+            .expect("Every level should have both stairs");
+        let players: Vec<Entity> = world.query_ref::<(), With<Player>>()
+            .map(|(player, _)| player)
+            .collect();
+        for player in players {
+            world.insert(player, Position::new(x, y));
+        }
+        Self::clear_stairs(world, &Position::new(x, y));
+        if let Some((layout, mut rng)) = layout {
+            world.populate_level(target, &layout, &mut rng);
+        }
+        Some(target)
+    }
+
+    // Moves a monster left waiting on the stairs the player arrived on to the
+    // nearest free tile.
+    fn clear_stairs(world: &mut World, pos: &Position) {
+        let Some(occupant) = world.entities_at(pos.x, pos.y)
+            .iter()
+            .copied()
+            .find(|entity| world.has::<BlocksTile>(*entity) && !world.has::<Player>(*entity)) else {
+            return;
+        };
+        let distances = distance_map(&world.map, pos);
+        let free = distances.iter()
+            .enumerate()
+            .filter_map(|(idx, distance)| Some((distance.filter(|distance| *distance > 0)?, world.map.idx_xy(idx))))
+            .filter(|(_, (y, x))| world.map.is_walkable(*x, *y) && world.blocking_at(*x, *y).is_none())
+            .min_by_key(|(distance, _)| *distance);
+        if let Some((_, (y, x))) = free {
+            world.insert(occupant, Position::new(x, y));
+        }
+    }
+
+    // Returns the level's RNG too, for `populate_level` to carry on from.
+    fn generate_map(world: &World, depth: usize) -> (Map, Layout, StdRng) {
+        let config = world.resource::<GameConfig>();
+        let mut rng = config.level_rng(depth);
+        let mut map = Map::new(config.map_width, config.map_height);
+        let generator = GeneratorKind::for_depth(&config.generators, depth).build(config, depth);
+        let layout = mapgen::generate_level(generator.as_ref(), &mut map, &mut rng);
+        (map, layout, rng)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{combat::AttackOutcome, components::Position, dungeon::StairsDirection, world::{Entity, World}};
+
+#[derive(Clone, Copy)]
+pub enum Action {
+    Move,
+    Attack,
+    UseDoor,
+    Rest
+}
+impl Action {
+    pub fn cost(self) -> usize {
+        match self {
+            Action::Move => 100,
+            Action::Attack => 100,
+            Action::UseDoor => 100,
+            Action::Rest => 50
+        }
+    }
+}
+
+pub struct ActionTaken {
+    pub entity: Entity,
+    pub action: Action
+}
+
+pub struct AttackEvent {
+    pub attacker: Entity,
+    pub defender: Entity
+}
+
+// Sent alongside `AttackOutcome` for every attack that hits.
+pub struct DamageDealt {
+    pub attacker: Entity,
+    pub defender: Entity,
+    pub amount: usize
+}
+
+// Sent for each entity whose HP hits 0; it is despawned later that frame.
+pub struct EntityDied {
+    pub entity: Entity
+}
+
+pub struct GoldPickedUp {
+    pub entity: Entity,
+    pub value: usize,
+    pub total: usize
+}
+
+pub struct Moved {
+    pub entity: Entity,
+    pub to: Position
+}
+
+// Closes the open doors next to `entity`.
+pub struct CloseDoors {
+    pub entity: Entity
+}
+
+pub struct UseStairs {
+    pub entity: Entity,
+    pub direction: StairsDirection
+}
+
+pub fn register_events(world: &mut World) {
+    world.add_event::<ActionTaken>();
+    world.add_event::<AttackEvent>();
+    world.add_event::<AttackOutcome>();
+    world.add_event::<CloseDoors>();
+    world.add_event::<DamageDealt>();
+    world.add_event::<EntityDied>();
+    world.add_event::<GoldPickedUp>();
+    world.add_event::<Moved>();
+    world.add_event::<UseStairs>();
+}
+
+// Events live for two frames so systems ordered before the writer still see them
+// next frame. Systems that must handle each event exactly once use `iter_current`.
+pub struct Events<E> {
+    current: Vec<E>,
+    previous: Vec<E>
+}
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self { current: vec![], previous: vec![] }
+    }
+}
+impl<E> Events<E> {
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &E> {
+        self.previous.iter().chain(self.current.iter())
+    }
+
+    pub fn iter_current(&self) -> impl Iterator<Item = &E> {
+        self.current.iter()
+    }
+
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+}
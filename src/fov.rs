@@ -0,0 +1,154 @@
+use crate::map::Map;
+
+// Symmetric shadowcasting: https://www.albertford.com/shadowcasting/
+// Slopes are kept as exact fractions so rounding never breaks symmetry.
+#[derive(Clone, Copy)]
+struct Slope {
+    num: i64,
+    den: i64
+}
+impl Slope {
+    fn new(num: i64, den: i64) -> Self {
+        Self { num, den }
+    }
+
+    fn between(col: i64, depth: i64) -> Self {
+        Self::new(2 * col - 1, 2 * depth)
+    }
+
+    // floor(depth * slope + 1/2)
+    fn round_ties_up(&self, depth: i64) -> i64 {
+        (2 * depth * self.num + self.den).div_euclid(2 * self.den)
+    }
+
+    // ceil(depth * slope - 1/2)
+    fn round_ties_down(&self, depth: i64) -> i64 {
+        -(-(2 * depth * self.num - self.den)).div_euclid(2 * self.den)
+    }
+}
+
+struct Row {
+    depth: i64,
+    start: Slope,
+    end: Slope
+}
+impl Row {
+    fn next(&self) -> Self {
+        Self { depth: self.depth + 1, start: self.start, end: self.end }
+    }
+
+    fn is_symmetric(&self, col: i64) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+}
+
+struct Quadrant {
+    cardinal: u8,
+    x: i64,
+    y: i64
+}
+impl Quadrant {
+    fn transform(&self, depth: i64, col: i64) -> (i64, i64) {
+        match self.cardinal {
+            0 => (self.x + col, self.y - depth),
+            1 => (self.x + depth, self.y + col),
+            2 => (self.x + col, self.y + depth),
+            _ => (self.x - depth, self.y + col)
+        }
+    }
+}
+
+fn blocks_sight(map: &Map, (x, y): (i64, i64)) -> bool {
+    x < 0 || y < 0 || map.blocks_sight(x as usize, y as usize)
+}
+
+fn in_radius(origin: (i64, i64), (x, y): (i64, i64), radius: i64) -> bool {
+    let (dx, dy) = (x - origin.0, y - origin.1);
+    dx * dx + dy * dy <= radius * radius
+}
+
+pub fn compute_fov(map: &Map, x: usize, y: usize, radius: usize) -> Vec<(usize, usize)> {
+    let origin = (x as i64, y as i64);
+    let radius = radius as i64;
+    let mut visible = vec![(x, y)];
+    let mut reveal = |(x, y): (i64, i64)| {
+        if x >= 0 && y >= 0 && map.in_bounds(x as usize, y as usize) && in_radius(origin, (x, y), radius) {
+            visible.push((x as usize, y as usize));
+        }
+    };
+    for cardinal in 0..4 {
+        let quadrant = Quadrant { cardinal, x: origin.0, y: origin.1 };
+        let mut rows = vec![Row { depth: 1, start: Slope::new(-1, 1), end: Slope::new(1, 1) }];
+        while let Some(mut row) = rows.pop() {
+            if row.depth > radius {
+                continue;
+            }
+            let mut previous: Option<bool> = None;
+            let min_col = row.start.round_ties_up(row.depth);
+            let max_col = row.end.round_ties_down(row.depth);
+            for col in min_col..=max_col {
+                let tile = quadrant.transform(row.depth, col);
+                let is_wall = blocks_sight(map, tile);
+                if is_wall || row.is_symmetric(col) {
+                    reveal(tile);
+                }
+                if previous == Some(true) && !is_wall {
+                    row.start = Slope::between(col, row.depth);
+                }
+                if previous == Some(false) && is_wall {
+                    let mut next = row.next();
+                    next.end = Slope::between(col, row.depth);
+                    rows.push(next);
+                }
+                previous = Some(is_wall);
+            }
+            if previous == Some(false) {
+                rows.push(row.next());
+            }
+        }
+    }
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::tile::Tile;
+
+    use super::*;
+
+    #[test]
+    fn floor_tiles_see_each_other_both_ways() {
+        const SIZE: usize = 21;
+        const RADIUS: usize = 8;
+        let mut map = Map::new(SIZE, SIZE);
+        for y in 1..SIZE - 1 {
+            for x in 1..SIZE - 1 {
+                // A grid of pillars, thinned out unevenly so no two shadows line up the same.
+                let pillar = x % 3 == 0 && y % 3 == 0 && (x * 7 + y * 3) % 5 != 0;
+                map.set_tile(x, y, if pillar { Tile::Wall } else { Tile::Floor });
+            }
+        }
+        let floors: Vec<(usize, usize)> = (0..SIZE)
+            .flat_map(|y| (0..SIZE).map(move |x| (x, y)))
+            .filter(|(x, y)| map.tile_at(*x, *y) == Some(Tile::Floor))
+            .collect();
+        let sees: Vec<HashSet<(usize, usize)>> = floors.iter()
+            .map(|(x, y)| compute_fov(&map, *x, *y, RADIUS).into_iter().collect())
+            .collect();
+        let mut hidden = 0;
+        for (a, a_sees) in floors.iter().zip(&sees) {
+            for (b, b_sees) in floors.iter().zip(&sees) {
+                if a.0.abs_diff(b.0).pow(2) + a.1.abs_diff(b.1).pow(2) > RADIUS * RADIUS {
+                    continue;
+                }
+                assert_eq!(a_sees.contains(b), b_sees.contains(a), "{a:?} and {b:?} disagree");
+                hidden += usize::from(!a_sees.contains(b));
+            }
+        }
+        // Make sure the pillars actually hide something.
+        assert!(hidden > 0);
+    }
+}
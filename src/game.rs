@@ -1,12 +1,57 @@
-use std::{io::stdout, thread, time::{Duration, Instant}};
+use std::{io::stdout, rc::Rc, thread, time::{Duration, Instant}};
 
 use crossterm::{cursor, execute, terminal::{disable_raw_mode, enable_raw_mode}};
+use rand::{rngs::StdRng, SeedableRng};
 
-use crate::{map::{BSPNode, Map}, systems::RenderSystem, world::World};
+use crate::{dungeon::Dungeon, events, map::Map, mapgen::{self, Corridors, GeneratorKind, PrefabLibrary}, raws::Raws, spawn::SpawnTables, systems::{self, FovSystem, TimeSystem}, world::{Entity, World}};
 
+#[derive(PartialEq, Clone, Copy)]
 pub enum TurnState {
     Player,
-    Enemy
+    Enemy,
+    // The player is dead and only quitting is left.
+    GameOver
+}
+
+pub struct GameConfig {
+    pub seed: u64,
+    pub map_width: usize,
+    pub map_height: usize,
+    pub bsp_depth: isize,
+    pub corridors: Corridors,
+    pub generators: Vec<GeneratorKind>,
+    pub prefabs: Rc<PrefabLibrary>,
+    pub target_fps: f32,
+    pub fov_radius: usize
+}
+
+impl GameConfig {
+    // Each level generates and populates itself from its own RNG, so a seed
+    // reproduces any depth no matter what was rolled on the way down.
+    pub fn level_rng(&self, depth: usize) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (depth as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+}
+
+#[derive(Default)]
+pub struct TurnCounter(pub u64);
+
+// Everything on a tile the player could see when `FovSystem` last ran.
+#[derive(Default)]
+pub struct InView(pub Vec<Entity>);
+
+#[derive(Default)]
+pub struct MessageLog {
+    messages: Vec<String>
+}
+impl MessageLog {
+    pub fn push(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    pub fn recent(&self, count: usize) -> &[String] {
+        &self.messages[self.messages.len().saturating_sub(count)..]
+    }
 }
 
 pub struct Game {
@@ -23,11 +68,42 @@ impl Drop for Game {
     }
 }
 impl Game {
-    pub fn new(width: usize, height: usize, depth: isize) -> Self {
-        let mut map = Map::new(width, height);        
-        BSPNode::create_dungeon(&mut map, depth);
+    const MAP_WIDTH: usize = 80;
+    const MAP_HEIGHT: usize = 30;
+    const BSP_DEPTH: isize = 4;
+
+    pub fn new(seed: u64, generators: Vec<GeneratorKind>, corridors: Corridors, prefabs: PrefabLibrary, raws: Raws) -> Self {
+        let rng = StdRng::seed_from_u64(seed);
+        let config = GameConfig {
+            seed,
+            map_width: Self::MAP_WIDTH,
+            map_height: Self::MAP_HEIGHT,
+            bsp_depth: Self::BSP_DEPTH,
+            corridors,
+            generators,
+            prefabs: Rc::new(prefabs),
+            target_fps: 8.0,
+            fov_radius: 8
+        };
+        let mut map = Map::new(config.map_width, config.map_height);        
+        let generator = GeneratorKind::for_depth(&config.generators, 0).build(&config, 0);
+        let mut level_rng = config.level_rng(0);
+        let layout = mapgen::generate_level(generator.as_ref(), &mut map, &mut level_rng);
         let mut world = World::new(map);
-        world.initialize();
+        world.insert_resource(config);
+        world.insert_resource(Dungeon::default());
+        world.insert_resource(TurnState::Player);
+        world.insert_resource(TurnCounter::default());
+        world.insert_resource(InView::default());
+        world.insert_resource(MessageLog::default());
+        world.insert_resource(SpawnTables::new(&raws));
+        world.insert_resource(raws);
+        world.insert_resource(rng);
+        events::register_events(&mut world);
+        systems::register_systems(&mut world.schedule);
+        world.initialize(&layout, &mut level_rng);
+        TimeSystem::run(&mut world);
+        FovSystem::run(&mut world);
         Self { world }
     }
 
@@ -35,20 +111,14 @@ impl Game {
         self.world.update()
     }
 
-    fn render(&self) -> std::io::Result<()> {
-        RenderSystem::render(&self.world)
-    }
-
     pub fn run(&mut self) -> std::io::Result<()> {
-        const TARGET_FPS: f32 = 8.0;
-        let frame_duration = Duration::from_secs_f32(1.0 / TARGET_FPS);
+        let frame_duration = Duration::from_secs_f32(1.0 / self.world.resource::<GameConfig>().target_fps);
         let mut frame_start: Instant;
         let mut elapsed: Duration;
         enable_raw_mode()?;
         execute!(stdout(), cursor::Hide)?;
         loop {
             frame_start = Instant::now();
-            self.render()?;
             self.update();
             elapsed = frame_start.elapsed();
             if elapsed < frame_duration {
@@ -1,13 +1,91 @@
 mod map;
+mod mapgen;
+mod dungeon;
+mod tile;
 mod world;
+mod storage;
+mod query;
+mod schedule;
+mod events;
+mod resources;
+mod pathfinding;
+mod fov;
+mod combat;
 mod components;
+mod dice;
+mod raws;
+mod spatial;
+mod spawn;
 mod systems;
 mod game;
 
+use std::path::PathBuf;
+
 use game::Game;
+use mapgen::{Corridors, GeneratorKind, PrefabLibrary, DEFAULT_PREFABS};
+use raws::{Raws, DEFAULT_RAWS};
+
+struct Args {
+    seed: Option<u64>,
+    // One generator per level; the last one is reused for every deeper level.
+    generators: Vec<GeneratorKind>,
+    corridors: Corridors,
+    prefabs: Option<PathBuf>,
+    monsters: Option<PathBuf>
+}
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut seed = None;
+        let mut generators = vec![GeneratorKind::Bsp];
+        let mut corridors = Corridors::default();
+        let mut prefabs = None;
+        let mut monsters = None;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg, None)
+            };
+            if !["--seed", "--generator", "--corridors", "--loops", "--prefabs", "--monsters"].contains(&name.as_str()) {
+                return Err(format!("unknown argument: {name}"));
+            }
+            let value = value.or_else(|| args.next()).ok_or(format!("{name} expects a value"))?;
+            match name.as_str() {
+                "--seed" => seed = Some(value.parse().map_err(|_| format!("invalid seed: {value}"))?),
+                "--generator" => generators = value.split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?,
+                "--corridors" => corridors.style = value.parse()?,
+                "--loops" => corridors.extra_connections = value.parse()
+                    .map_err(|_| format!("invalid loop count: {value}"))?,
+                "--prefabs" => prefabs = Some(PathBuf::from(value)),
+                _ => monsters = Some(PathBuf::from(value))
+            }
+        }
+        Ok(Self { seed, generators, corridors, prefabs, monsters })
+    }
+}
 
 fn main() {
-    let mut game = Game::new(80, 30, 4);
+    let (args, prefabs, raws) = match Args::parse().and_then(|args| {
+        let prefabs = match &args.prefabs {
+            Some(path) => PrefabLibrary::load(path)?,
+            None => PrefabLibrary::parse(DEFAULT_PREFABS).map_err(|e| format!("built-in prefabs:{e}"))?
+        };
+        let raws = match &args.monsters {
+            Some(path) => Raws::load(path)?,
+            None => Raws::parse(DEFAULT_RAWS).map_err(|e| format!("built-in monsters:{e}"))?
+        };
+        Ok((args, prefabs, raws))
+    }) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        }
+    };
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut game = Game::new(seed, args.generators, args.corridors, prefabs, raws);
     if let Err(e) = game.run() {
         eprintln!("error: {e}");
         std::process::exit(1);
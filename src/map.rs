@@ -1,9 +1,11 @@
-use std::{cmp::{max, min}, fmt::Display, io::{stdout, Stdout}};
+use std::{fmt::Display, io::{stdout, Stdout}};
 
 use crossterm::{cursor::MoveTo, style::Print, QueueableCommand};
 use rand::Rng;
 
-#[derive(Debug, Clone)]
+use crate::{mapgen::Corridors, tile::Tile};
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Rect {
     x: usize,
     y: usize,
@@ -33,6 +35,35 @@ impl Rect {
         (self.x + self.width / 2, self.y + self.height / 2)
     }
 
+    // The area a carved room turns into floor, as (x, y, width, height).
+    pub fn interior_bounds(&self) -> (usize, usize, usize, usize) {
+        (self.x + 1, self.y + 1, self.width - 1, self.height - 1)
+    }
+
+    // The tiles a carved room turns into floor; the rect's own edge stays wall.
+    pub fn interior(&self) -> impl Iterator<Item = (usize, usize)> + use<> {
+        let (x, y, width, height) = (self.x, self.y, self.width, self.height);
+        ((y + 1)..(y + height)).flat_map(move |y| ((x + 1)..(x + width)).map(move |x| (x, y)))
+    }
+
+    // The wall ring around the interior, without its corners.
+    pub fn edge(&self) -> impl Iterator<Item = (usize, usize)> + use<> {
+        let (x, y, width, height) = (self.x, self.y, self.width, self.height);
+        let horizontal = ((x + 1)..(x + width)).flat_map(move |x| [(x, y), (x, y + height)]);
+        let vertical = ((y + 1)..(y + height)).flat_map(move |y| [(x, y), (x + width, y)]);
+        horizontal.chain(vertical)
+    }
+
+    // Whether (`x`, `y`) is inside the rect or on its edge.
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..=self.x + self.width).contains(&x) && (self.y..=self.y + self.height).contains(&y)
+    }
+
+    pub fn is_edge(&self, x: usize, y: usize) -> bool {
+        self.contains(x, y)
+            && (x == self.x || x == self.x + self.width || y == self.y || y == self.y + self.height)
+    }
+
     pub fn intersects(&self, other: &Self) -> bool {
         self.x <= other.x + other.width
             && self.x + self.width >= other.x
@@ -57,15 +88,14 @@ impl BSPNode {
     }
 
     fn new(rect: Rect) -> Self {
-        Self { rect: rect, left: None, right: None, room: None }
+        Self { rect, left: None, right: None, room: None }
     }
 
     pub fn is_leaf(&self) -> bool {
         self.left.is_none() && self.right.is_none()
     }
 
-    fn split(&mut self) -> bool {
-        let mut rng = rand::rng();     
+    fn split<R: Rng>(&mut self, rng: &mut R) -> bool {
         if self.left.is_some() || self.right.is_some() {
             return false;
         }         
@@ -128,22 +158,21 @@ impl BSPNode {
         true
     }
 
-    pub fn split_recursively(&mut self, depth: isize) {
+    pub fn split_recursively<R: Rng>(&mut self, depth: isize, rng: &mut R) {
         if depth <= 0 {
             return;
         }
-        if self.split() {
+        if self.split(rng) {
             if let Some(left) = &mut self.left {
-                left.split_recursively(depth - 1);
+                left.split_recursively(depth - 1, rng);
             }
             if let Some(right) = &mut self.right {
-                right.split_recursively(depth - 1);
+                right.split_recursively(depth - 1, rng);
             }
         }
     }
 
-    fn carve_room(&mut self, carved_rooms: &[Rect]) -> bool {
-        let mut rng = rand::rng();
+    fn carve_room<R: Rng>(&mut self, carved_rooms: &[Rect], rng: &mut R) -> bool {
         if !self.is_leaf() {
             return false;
         }
@@ -162,55 +191,40 @@ impl BSPNode {
         true
     }
 
-    pub fn carve_all_rooms(&mut self) -> Vec<Rect> {
+    pub fn carve_all_rooms<R: Rng>(&mut self, rng: &mut R) -> Vec<Rect> {
         let mut carved_rooms: Vec<Rect> = Vec::new();                
         self.traverse_pre_order_mut(&mut |node| {            
-            if node.carve_room(&carved_rooms) {
-                if let Some(room) = &node.room {
-                    carved_rooms.push(room.clone());
-                }                
+            if node.carve_room(&carved_rooms, rng)
+                && let Some(room) = &node.room {
+                carved_rooms.push(room.clone());
             }            
         });
         carved_rooms
     }
 
-    fn create_corridor(room1: &Rect, room2: &Rect, map: &mut Map) {
-        let ((x1, y1), (x2, y2)) = (room1.center(), room2.center());
-        for x in min(x1, x2)..=max(x1, x2) {
-            map.set_tile(x, y1, '.');
-        }
-        for y in min(y1, y2)..=max(y1, y2) {
-            map.set_tile(x2, y, '.');
-        }
-    }
-
-    pub fn create_all_corridors(&mut self, map: &mut Map) {        
+    pub fn create_all_corridors<R: Rng>(&mut self, map: &mut Map, corridors: &Corridors, rooms: &[Rect], rng: &mut R) {
         if let (Some(left), Some(right)) = (&mut self.left, &mut self.right) {
             if let (Some(l), Some(r)) = (&left.room, &right.room) {
-                Self::create_corridor(l, r, map);
+                corridors.dig(map, l, r, rooms, rng);
             }
-            left.create_all_corridors(map);
-            right.create_all_corridors(map);            
+            left.create_all_corridors(map, corridors, rooms, rng);
+            right.create_all_corridors(map, corridors, rooms, rng);
         }
     }
 
     pub fn collect_rooms(&self, rooms: &mut Vec<Rect>) {
-        if let Some(room) = &self.room {
-            rooms.push(room.clone());
-        }
-        if let Some(left) = &self.left {
-            left.collect_rooms(rooms);
-        }
-        if let Some(right) = &self.right {
-            right.collect_rooms(rooms);
-        }
+        self.traverse_pre_order(&mut |node| {
+            if let Some(room) = &node.room {
+                rooms.push(room.clone());
+            }
+        });
     }
 
-    fn connect_rooms_in_sequence(&self, map: &mut Map) {
+    fn connect_rooms_in_sequence<R: Rng>(&self, map: &mut Map, corridors: &Corridors, rng: &mut R) {
         let mut rooms: Vec<Rect> = Vec::new();
         self.collect_rooms(&mut rooms);
         for i in 1..rooms.len() {
-            Self::create_corridor(&rooms[i - 1], &rooms[i], map);
+            corridors.dig(map, &rooms[i - 1], &rooms[i], &rooms, rng);
         }
     }
 
@@ -234,46 +248,96 @@ impl BSPNode {
         }
     }
 
-    pub fn create_dungeon(map: &mut Map, depth: isize) {
+    pub fn create_dungeon<R: Rng>(map: &mut Map, depth: isize, corridors: &Corridors, rng: &mut R) -> Vec<Rect> {
         let mut root = Self::root(map);
-        root.split_recursively(depth);        
-        let carved_rooms = root.carve_all_rooms();        
-        for room in carved_rooms {
-            for y in (room.y + 1)..(room.y + room.height) {
-                for x in (room.x + 1)..(room.x + room.width) {
-                    map.set_tile(x, y, '.');
+        root.split_recursively(depth, rng);        
+        let carved_rooms = root.carve_all_rooms(rng);        
+        for room in &carved_rooms {
+            for (x, y) in room.interior() {
+                map.set_tile(x, y, Tile::Floor);
+            }
+        }
+        root.create_all_corridors(map, corridors, &carved_rooms, rng);
+        root.connect_rooms_in_sequence(map, corridors, rng);
+        corridors.add_loops(map, &carved_rooms, rng);
+        Self::place_doors(map, &carved_rooms);
+        Self::place_stairs(map, &carved_rooms);
+        carved_rooms
+    }
+
+    // Corridors punch straight through room walls; every gap that has wall on
+    // both sides along the edge becomes a closed door.
+    fn place_doors(map: &mut Map, rooms: &[Rect]) {
+        let is_wall = |map: &Map, x: usize, y: usize| map.tile_at(x, y) == Some(Tile::Wall);
+        for room in rooms {
+            for (x, y) in room.edge() {
+                if map.tile_at(x, y) != Some(Tile::Corridor) {
+                    continue;
+                }
+                let (left, right) = (x.checked_sub(1).is_some_and(|left| is_wall(map, left, y)), is_wall(map, x + 1, y));
+                let (up, down) = (y.checked_sub(1).is_some_and(|up| is_wall(map, x, up)), is_wall(map, x, y + 1));
+                if (left && right && !up && !down) || (up && down && !left && !right) {
+                    map.set_tile(x, y, Tile::DoorClosed);
                 }
             }
         }
-        root.create_all_corridors(map);
-        root.connect_rooms_in_sequence(map);
+    }
+
+    // Up stairs go in the centre of the first room and down stairs in the last one,
+    // falling back to the room's corner when there is only a single room.
+    fn place_stairs(map: &mut Map, rooms: &[Rect]) {
+        let (Some(first), Some(last)) = (rooms.first(), rooms.last()) else {
+            return;
+        };
+        let (up_x, up_y) = first.center();
+        map.set_tile(up_x, up_y, Tile::StairsUp);
+        let (down_x, down_y) = if rooms.len() > 1 { last.center() } else { (last.x + 1, last.y + 1) };
+        map.set_tile(down_x, down_y, Tile::StairsDown);
     }
 }
 
 #[derive(Debug)]
 pub struct Map {
-    tiles: Vec<char>,
+    tiles: Vec<Tile>,
+    visible: Vec<bool>,
+    revealed: Vec<bool>,
     stride: usize
 }
 impl Map {    
     pub fn new(width: usize, height: usize) -> Self {
         Self { 
-            tiles: vec!['#'; width * height], 
+            tiles: vec![Tile::Wall; width * height], 
+            visible: vec![false; width * height],
+            revealed: vec![false; width * height],
             stride: width 
         }
     }
 
-    pub fn get_tile(&self, idx: usize) -> Option<char> {
+    pub fn get_tile(&self, idx: usize) -> Option<Tile> {
         self.tiles.get(idx).cloned()
     }    
 
-    pub fn set_tile(&mut self, x: usize, y: usize, tile: char) {
+    pub fn tile_at(&self, x: usize, y: usize) -> Option<Tile> {
+        if !self.in_bounds(x, y) {
+            return None;
+        }
+        self.get_tile(self.xy_idx(x, y))
+    }
+
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: Tile) {
         let idx = self.xy_idx(x, y);
         if let Some(t) = self.tiles.get_mut(idx) {
             *t = tile;
         };
     }
 
+    // Corridors only replace walls so they never overwrite room floors or features.
+    pub fn carve_corridor(&mut self, x: usize, y: usize) {
+        if self.tile_at(x, y) == Some(Tile::Wall) {
+            self.set_tile(x, y, Tile::Corridor);
+        }
+    }
+
     pub fn xy_idx(&self, x: usize, y: usize) -> usize {
         y * self.stride + x
     }
@@ -292,16 +356,53 @@ impl Map {
         self.stride
     }
 
+    pub fn in_bounds(&self, x: usize, y: usize) -> bool {
+        x < self.columns() && y < self.rows()
+    }
+
     pub fn is_walkable(&self, x: usize, y: usize) -> bool {
-        let idx = self.xy_idx(x, y);        
-        if let Some(ch) = self.get_tile(idx) {
-            ch == '.'
-        } else {
-            false
+        self.tile_at(x, y).is_some_and(Tile::is_walkable)
+    }
+
+    pub fn is_passable(&self, x: usize, y: usize) -> bool {
+        self.tile_at(x, y).is_some_and(Tile::is_passable)
+    }
+
+    pub fn blocks_sight(&self, x: usize, y: usize) -> bool {
+        self.tile_at(x, y).is_none_or(Tile::blocks_sight)
+    }
+
+    pub fn movement_cost(&self, x: usize, y: usize) -> Option<usize> {
+        self.tile_at(x, y).and_then(Tile::movement_cost)
+    }
+
+    pub fn is_visible(&self, x: usize, y: usize) -> bool {
+        self.in_bounds(x, y) && self.visible[self.xy_idx(x, y)]
+    }
+
+    pub fn is_revealed(&self, x: usize, y: usize) -> bool {
+        self.in_bounds(x, y) && self.revealed[self.xy_idx(x, y)]
+    }
+
+    pub fn clear_visible(&mut self) {
+        self.visible.fill(false);
+    }
+
+    pub fn set_visible(&mut self, x: usize, y: usize) {
+        if self.in_bounds(x, y) {
+            let idx = self.xy_idx(x, y);
+            self.visible[idx] = true;
+            self.revealed[idx] = true;
         }
     }
 
-    pub fn get_tiles(&self) -> &[char] {
+    pub fn find_tile(&self, tile: Tile) -> Option<(usize, usize)> {
+        let idx = self.tiles.iter().position(|t| *t == tile)?;
+        let (y, x) = self.idx_xy(idx);
+        Some((x, y))
+    }
+
+    pub fn get_tiles(&self) -> &[Tile] {
         &self.tiles
     }
 }
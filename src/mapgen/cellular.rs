@@ -0,0 +1,89 @@
+use rand::{rngs::StdRng, Rng};
+
+use crate::{map::Map, tile::Tile};
+
+use super::{cull_disconnected, floor_tiles, place_stairs_apart, voronoi_regions, Layout, MapGenerator};
+
+// Caves grown from random noise: every smoothing pass turns a tile into wall when
+// most of its eight neighbours are walls, and into floor when most are open.
+pub struct CellularAutomataGenerator {
+    pub fill_percent: u32,
+    pub iterations: usize,
+    // Caves whose largest pocket is smaller than this share of the map are regenerated.
+    pub min_floor_percent: u32,
+    pub max_attempts: usize,
+    pub spawn_regions: usize
+}
+impl Default for CellularAutomataGenerator {
+    fn default() -> Self {
+        Self { fill_percent: 45, iterations: 5, min_floor_percent: 30, max_attempts: 10, spawn_regions: 8 }
+    }
+}
+impl CellularAutomataGenerator {
+    fn is_border(map: &Map, x: usize, y: usize) -> bool {
+        x == 0 || y == 0 || x + 1 == map.columns() || y + 1 == map.rows()
+    }
+
+    fn fill(&self, map: &mut Map, rng: &mut StdRng) {
+        for y in 0..map.rows() {
+            for x in 0..map.columns() {
+                let wall = Self::is_border(map, x, y) || rng.random_range(0..100) < self.fill_percent;
+                map.set_tile(x, y, if wall { Tile::Wall } else { Tile::Floor });
+            }
+        }
+    }
+
+    // Out-of-bounds neighbours count as walls so caves never open onto the edge.
+    fn wall_neighbours(map: &Map, x: usize, y: usize) -> usize {
+        let mut walls = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) == (0, 0) {
+                    continue;
+                }
+                let wall = match (x.checked_add_signed(dx), y.checked_add_signed(dy)) {
+                    (Some(x), Some(y)) => map.tile_at(x, y).is_none_or(|tile| tile == Tile::Wall),
+                    _ => true
+                };
+                walls += usize::from(wall);
+            }
+        }
+        walls
+    }
+
+    fn smooth(map: &mut Map) {
+        let mut next = vec![];
+        for y in 0..map.rows() {
+            for x in 0..map.columns() {
+                let walls = Self::wall_neighbours(map, x, y);
+                let tile = match map.tile_at(x, y) {
+                    _ if Self::is_border(map, x, y) => Tile::Wall,
+                    _ if walls > 4 => Tile::Wall,
+                    _ if walls < 4 => Tile::Floor,
+                    tile => tile.unwrap_or(Tile::Wall)
+                };
+                next.push((x, y, tile));
+            }
+        }
+        for (x, y, tile) in next {
+            map.set_tile(x, y, tile);
+        }
+    }
+}
+impl MapGenerator for CellularAutomataGenerator {
+    fn generate(&self, map: &mut Map, rng: &mut StdRng) -> Layout {
+        let min_floor = map.rows() * map.columns() * self.min_floor_percent as usize / 100;
+        for _ in 0..self.max_attempts.max(1) {
+            self.fill(map, rng);
+            for _ in 0..self.iterations {
+                Self::smooth(map);
+            }
+            cull_disconnected(map);
+            if floor_tiles(map).len() >= min_floor {
+                break;
+            }
+        }
+        place_stairs_apart(map, rng);
+        voronoi_regions(map, self.spawn_regions, rng).into()
+    }
+}
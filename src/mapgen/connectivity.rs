@@ -0,0 +1,142 @@
+use crate::{components::Position, map::Map, pathfinding::distance_map};
+
+use super::dig_between;
+
+// Passable tiles that cannot be reached from `start`, even by opening doors.
+pub fn unreachable_tiles(map: &Map, start: &Position) -> Vec<Position> {
+    distance_map(map, start)
+        .iter()
+        .enumerate()
+        .filter(|(idx, distance)| distance.is_none() && map.get_tile(*idx).is_some_and(|tile| tile.is_passable()))
+        .map(|(idx, _)| {
+            let (y, x) = map.idx_xy(idx);
+            Position::new(x, y)
+        })
+        .collect()
+}
+
+// Connects every unreachable pocket to the area reachable from `start` with a
+// corridor. Returns how many corridors had to be dug.
+pub fn repair_connectivity(map: &mut Map, start: &Position) -> usize {
+    let mut dug = 0;
+    loop {
+        let unreachable = unreachable_tiles(map, start);
+        if unreachable.is_empty() {
+            return dug;
+        }
+        let reachable: Vec<Position> = distance_map(map, start)
+            .iter()
+            .enumerate()
+            .filter(|(_, distance)| distance.is_some())
+            .map(|(idx, _)| {
+                let (y, x) = map.idx_xy(idx);
+                Position::new(x, y)
+            })
+            .collect();
+        if reachable.is_empty() {
+            return dug;
+        }
+        dig_between(map, &unreachable, &reachable);
+        dug += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{game::GameConfig, mapgen::{corridors::CorridorStyle, generate_level, Corridors, DlaMode, GeneratorKind, PrefabLibrary, DEFAULT_PREFABS}, tile::Tile};
+
+    use super::*;
+
+    fn config(generator: GeneratorKind) -> GameConfig {
+        GameConfig {
+            seed: 0,
+            map_width: 80,
+            map_height: 30,
+            bsp_depth: 4,
+            corridors: Corridors::default(),
+            generators: vec![generator],
+            prefabs: Rc::new(PrefabLibrary::parse(DEFAULT_PREFABS).expect("Built-in prefabs should parse")),
+            target_fps: 8.0,
+            fov_radius: 8
+        }
+    }
+
+    // Generates `seeds` levels, spread over the first few depths, and checks that
+    // every walkable tile can be reached from where the player arrives. Without
+    // `repair` the generator has to manage that on its own.
+    fn assert_connected(config: &GameConfig, seeds: u64, repair: bool) {
+        let generator = config.generators[0];
+        for seed in 0..seeds {
+            let level = (seed % 6) as usize;
+            let mut map = Map::new(config.map_width, config.map_height);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let built = generator.build(config, level);
+            if repair {
+                generate_level(built.as_ref(), &mut map, &mut rng);
+            } else {
+                built.generate(&mut map, &mut rng);
+            }
+            let (x, y) = map.find_tile(Tile::StairsUp)
+                .unwrap_or_else(|| panic!("{generator:?} seed {seed}: no up stairs"));
+            assert!(map.find_tile(Tile::StairsDown).is_some(), "{generator:?} seed {seed}: no down stairs");
+            let unreachable = unreachable_tiles(&map, &Position::new(x, y));
+            assert!(unreachable.is_empty(), "{generator:?} seed {seed}: {} unreachable tiles, first at {:?}",
+                unreachable.len(), (unreachable[0].x, unreachable[0].y));
+        }
+    }
+
+    #[test]
+    fn bsp_levels_are_connected() {
+        assert_connected(&config(GeneratorKind::Bsp), 3000, true);
+    }
+
+    #[test]
+    fn bsp_corridors_connect_every_room() {
+        for style in [CorridorStyle::Straight, CorridorStyle::Routed, CorridorStyle::Winding] {
+            let mut config = config(GeneratorKind::Bsp);
+            config.corridors = Corridors { style, wide_chance: 0.5, extra_connections: 3 };
+            assert_connected(&config, 1000, false);
+        }
+    }
+
+    #[test]
+    fn cave_levels_are_connected() {
+        assert_connected(&config(GeneratorKind::Caves), 1000, true);
+    }
+
+    #[test]
+    fn drunkard_levels_are_connected() {
+        assert_connected(&config(GeneratorKind::Drunkard), 1000, true);
+    }
+
+    #[test]
+    fn dla_levels_are_connected() {
+        for mode in [DlaMode::WalkInwards, DlaMode::WalkOutwards, DlaMode::CentralAttractor] {
+            assert_connected(&config(GeneratorKind::Dla(mode)), 400, true);
+        }
+    }
+
+    #[test]
+    fn wfc_levels_are_connected() {
+        assert_connected(&config(GeneratorKind::Wfc), 200, true);
+    }
+
+    #[test]
+    fn repair_digs_to_sealed_pockets() {
+        let mut map = Map::new(20, 10);
+        for x in 1..6 {
+            map.set_tile(x, 2, Tile::Floor);
+        }
+        for (x, y) in [(12, 7), (13, 7), (17, 2)] {
+            map.set_tile(x, y, Tile::Floor);
+        }
+        let start = Position::new(1, 2);
+        assert_eq!(unreachable_tiles(&map, &start).len(), 3);
+        assert_eq!(repair_connectivity(&mut map, &start), 2);
+        assert!(unreachable_tiles(&map, &start).is_empty());
+    }
+}
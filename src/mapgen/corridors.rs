@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+use rand::Rng;
+
+use crate::{components::Position, map::{Map, Rect}, pathfinding::a_star_by};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CorridorStyle {
+    // An L-shape between room centres, cutting through whatever is in the way.
+    Straight,
+    // The cheapest path that goes around other rooms, reusing corridors already dug.
+    Routed,
+    // A random walk that drifts towards the other room.
+    Winding
+}
+impl FromStr for CorridorStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "straight" => Ok(CorridorStyle::Straight),
+            "routed" => Ok(CorridorStyle::Routed),
+            "winding" => Ok(CorridorStyle::Winding),
+            _ => Err(format!("unknown corridor style: {s}"))
+        }
+    }
+}
+
+// How a room-based generator joins its rooms together.
+#[derive(Clone, Copy, Debug)]
+pub struct Corridors {
+    pub style: CorridorStyle,
+    // Chance of a corridor being dug two tiles wide.
+    pub wide_chance: f64,
+    // Corridors added between nearby rooms once every room is connected, so the
+    // level has loops instead of a dead end at every branch.
+    pub extra_connections: usize
+}
+impl Default for Corridors {
+    fn default() -> Self {
+        Self { style: CorridorStyle::Routed, wide_chance: 0.15, extra_connections: 2 }
+    }
+}
+impl Corridors {
+    // Digs a corridor between the centres of `from` and `to`. `rooms` are the
+    // rooms a routed corridor has to go around.
+    pub fn dig<R: Rng>(&self, map: &mut Map, from: &Rect, to: &Rect, rooms: &[Rect], rng: &mut R) {
+        let wide = rng.random_bool(self.wide_chance);
+        let (start, goal) = (from.center(), to.center());
+        let path = match self.style {
+            CorridorStyle::Straight => l_path(start, goal),
+            CorridorStyle::Routed => Self::route(map, from, to, rooms, wide).unwrap_or_else(|| l_path(start, goal)),
+            CorridorStyle::Winding => Self::wind(map, start, goal, rng)
+        };
+        for (x, y) in path {
+            map.carve_corridor(x, y);
+            if wide && x + 2 < map.columns() && y + 2 < map.rows() {
+                map.carve_corridor(x + 1, y);
+                map.carve_corridor(x, y + 1);
+                map.carve_corridor(x + 1, y + 1);
+            }
+        }
+    }
+
+    // Adds `extra_connections` corridors, each from a random room to the nearest
+    // room it isn't already joined to in `rooms` order.
+    pub fn add_loops<R: Rng>(&self, map: &mut Map, rooms: &[Rect], rng: &mut R) {
+        let mut joined: Vec<(usize, usize)> = (1..rooms.len()).map(|i| (i - 1, i)).collect();
+        for _ in 0..self.extra_connections {
+            if rooms.len() < 3 {
+                return;
+            }
+            let a = rng.random_range(0..rooms.len());
+            let centre = rooms[a].center();
+            let nearest = (0..rooms.len())
+                .filter(|b| *b != a && !joined.contains(&(a.min(*b), a.max(*b))))
+                .min_by_key(|b| {
+                    let other = rooms[*b].center();
+                    centre.0.abs_diff(other.0) + centre.1.abs_diff(other.1)
+                });
+            let Some(b) = nearest else {
+                continue;
+            };
+            joined.push((a.min(b), a.max(b)));
+            self.dig(map, &rooms[a], &rooms[b], rooms, rng);
+        }
+    }
+
+    fn route(map: &Map, from: &Rect, to: &Rect, rooms: &[Rect], wide: bool) -> Option<Vec<(usize, usize)>> {
+        let (start, goal) = (from.center(), to.center());
+        let brush: &[(usize, usize)] = if wide { &[(0, 0), (1, 0), (0, 1), (1, 1)] } else { &[(0, 0)] };
+        let cost = |pos: &Position| -> Option<usize> {
+            if pos.x == 0 || pos.y == 0 || pos.x + 2 > map.columns() || pos.y + 2 > map.rows() {
+                return None;
+            }
+            for (dx, dy) in brush {
+                let (x, y) = (pos.x + dx, pos.y + dy);
+                if rooms.iter().any(|room| room != from && room != to && room.contains(x, y)) {
+                    return None;
+                }
+            }
+            // Running along a room's wall would leave no doorway, so it costs extra.
+            if [from, to].iter().any(|room| room.is_edge(pos.x, pos.y)) {
+                return Some(4);
+            }
+            Some(if map.is_passable(pos.x, pos.y) { 1 } else { 2 })
+        };
+        let path = a_star_by(map, &Position::new(start.0, start.1), &Position::new(goal.0, goal.1), cost)?;
+        Some(path.into_iter().map(|pos| (pos.x, pos.y)).collect())
+    }
+
+    // Steps towards the goal most of the time and in a random direction otherwise,
+    // finishing with a straight run if the walk hasn't arrived after a while.
+    fn wind<R: Rng>(map: &Map, start: (usize, usize), goal: (usize, usize), rng: &mut R) -> Vec<(usize, usize)> {
+        let (mut x, mut y) = start;
+        let mut path = vec![start];
+        let limit = 4 * (start.0.abs_diff(goal.0) + start.1.abs_diff(goal.1)) + 20;
+        while (x, y) != goal && path.len() < limit {
+            let (dx, dy): (isize, isize) = if rng.random_bool(0.6) {
+                let horizontal = y == goal.1 || (x != goal.0 && rng.random_bool(0.5));
+                if horizontal {
+                    (if goal.0 > x { 1 } else { -1 }, 0)
+                } else {
+                    (0, if goal.1 > y { 1 } else { -1 })
+                }
+            } else {
+                [(0, -1), (-1, 0), (0, 1), (1, 0)][rng.random_range(0..4)]
+            };
+            x = x.saturating_add_signed(dx).clamp(1, map.columns().saturating_sub(2).max(1));
+            y = y.saturating_add_signed(dy).clamp(1, map.rows().saturating_sub(2).max(1));
+            path.push((x, y));
+        }
+        path.extend(l_path((x, y), goal));
+        path
+    }
+}
+
+// The tiles of an L-shaped path, along the row of `from` then the column of `to`.
+pub fn l_path(from: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    let horizontal = (from.0.min(to.0)..=from.0.max(to.0)).map(|x| (x, from.1));
+    let vertical = (from.1.min(to.1)..=from.1.max(to.1)).map(|y| (to.0, y));
+    horizontal.chain(vertical).collect()
+}
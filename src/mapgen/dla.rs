@@ -0,0 +1,119 @@
+use std::cmp::Ordering;
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::{components::Position, map::Map, tile::Tile};
+
+use super::{cull_disconnected, floor_percent, map_centre, place_stairs_apart, random_step, voronoi_regions, Layout, MapGenerator};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DlaMode {
+    // Particles wander in from a random wall until they bump into the cave.
+    WalkInwards,
+    // Particles leave the centre and dig the first wall they hit.
+    WalkOutwards,
+    // Particles head straight for the centre from a random wall.
+    CentralAttractor
+}
+
+// Diffusion-limited aggregation: the cave grows from a seed in the middle of the
+// map, one particle at a time, until enough of the map is open.
+pub struct DlaGenerator {
+    pub mode: DlaMode,
+    pub target_floor_percent: usize,
+    // Caps the number of particles so a tiny target map cannot loop forever.
+    pub max_particles: usize,
+    pub spawn_regions: usize
+}
+impl Default for DlaGenerator {
+    fn default() -> Self {
+        Self { mode: DlaMode::WalkInwards, target_floor_percent: 25, max_particles: 20_000, spawn_regions: 8 }
+    }
+}
+impl DlaGenerator {
+    fn random_interior(map: &Map, rng: &mut StdRng) -> Position {
+        Position::new(
+            rng.random_range(1..map.columns().saturating_sub(1).max(2)),
+            rng.random_range(1..map.rows().saturating_sub(1).max(2))
+        )
+    }
+
+    fn is_floor(map: &Map, pos: &Position) -> bool {
+        map.tile_at(pos.x, pos.y) == Some(Tile::Floor)
+    }
+
+    // Returns the tile the particle settles on, if any.
+    fn release(&self, map: &Map, centre: &Position, rng: &mut StdRng) -> Option<Position> {
+        match self.mode {
+            DlaMode::WalkInwards => {
+                let mut pos = Self::random_interior(map, rng);
+                if Self::is_floor(map, &pos) {
+                    return None;
+                }
+                loop {
+                    let next = random_step(map, &pos, rng);
+                    if Self::is_floor(map, &next) {
+                        return Some(pos);
+                    }
+                    pos = next;
+                }
+            },
+            DlaMode::WalkOutwards => {
+                let mut pos = centre.clone();
+                while Self::is_floor(map, &pos) {
+                    pos = random_step(map, &pos, rng);
+                }
+                Some(pos)
+            },
+            DlaMode::CentralAttractor => {
+                let mut pos = Self::random_interior(map, rng);
+                if Self::is_floor(map, &pos) {
+                    return None;
+                }
+                loop {
+                    let next = Self::towards(&pos, centre);
+                    if Self::is_floor(map, &next) {
+                        return Some(pos);
+                    }
+                    pos = next;
+                }
+            }
+        }
+    }
+
+    // Steps along whichever axis is farther from `target`.
+    fn towards(pos: &Position, target: &Position) -> Position {
+        let step = |from: usize, to: usize| match from.cmp(&to) {
+            Ordering::Less => from + 1,
+            Ordering::Greater => from - 1,
+            Ordering::Equal => from
+        };
+        if pos.x.abs_diff(target.x) >= pos.y.abs_diff(target.y) {
+            Position::new(step(pos.x, target.x), pos.y)
+        } else {
+            Position::new(pos.x, step(pos.y, target.y))
+        }
+    }
+}
+impl MapGenerator for DlaGenerator {
+    fn generate(&self, map: &mut Map, rng: &mut StdRng) -> Layout {
+        let centre = map_centre(map);
+        map.set_tile(centre.x, centre.y, Tile::Floor);
+        for (dx, dy) in [(0, -1), (-1, 0), (0, 1), (1, 0)] {
+            if let (Some(x), Some(y)) = (centre.x.checked_add_signed(dx), centre.y.checked_add_signed(dy)) {
+                map.set_tile(x, y, Tile::Floor);
+            }
+        }
+        for _ in 0..self.max_particles {
+            if floor_percent(map) >= self.target_floor_percent {
+                break;
+            }
+            if let Some(pos) = self.release(map, &centre, rng) {
+                map.set_tile(pos.x, pos.y, Tile::Floor);
+            }
+        }
+        cull_disconnected(map);
+        place_stairs_apart(map, rng);
+        voronoi_regions(map, self.spawn_regions, rng).into()
+    }
+}
@@ -0,0 +1,40 @@
+use rand::{rngs::StdRng, Rng};
+
+use crate::{map::Map, tile::Tile};
+
+use super::{cull_disconnected, floor_percent, floor_tiles, map_centre, place_stairs_apart, random_step, voronoi_regions, Layout, MapGenerator};
+
+// Walkers stumble around carving floor until enough of the map is open. The first
+// walker starts in the centre and later ones start on floor that is already dug,
+// so the result is always a single connected cave.
+pub struct DrunkardsWalkGenerator {
+    pub walkers: usize,
+    pub lifetime: usize,
+    pub target_floor_percent: usize,
+    pub spawn_regions: usize
+}
+impl Default for DrunkardsWalkGenerator {
+    fn default() -> Self {
+        Self { walkers: 400, lifetime: 200, target_floor_percent: 40, spawn_regions: 8 }
+    }
+}
+impl MapGenerator for DrunkardsWalkGenerator {
+    fn generate(&self, map: &mut Map, rng: &mut StdRng) -> Layout {
+        let centre = map_centre(map);
+        map.set_tile(centre.x, centre.y, Tile::Floor);
+        for _ in 0..self.walkers {
+            if floor_percent(map) >= self.target_floor_percent {
+                break;
+            }
+            let floors = floor_tiles(map);
+            let mut pos = floors[rng.random_range(0..floors.len())].clone();
+            for _ in 0..self.lifetime {
+                map.set_tile(pos.x, pos.y, Tile::Floor);
+                pos = random_step(map, &pos, rng);
+            }
+        }
+        cull_disconnected(map);
+        place_stairs_apart(map, rng);
+        voronoi_regions(map, self.spawn_regions, rng).into()
+    }
+}
@@ -0,0 +1,264 @@
+mod cellular;
+mod connectivity;
+mod corridors;
+mod dla;
+mod drunkard;
+mod prefab;
+mod wfc;
+
+use std::{rc::Rc, str::FromStr};
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::{components::Position, game::GameConfig, map::{BSPNode, Map}, pathfinding::{distance_map, manhattan_distance}, tile::Tile};
+
+pub use cellular::CellularAutomataGenerator;
+pub use connectivity::repair_connectivity;
+pub use corridors::Corridors;
+pub use dla::{DlaGenerator, DlaMode};
+pub use drunkard::DrunkardsWalkGenerator;
+pub use prefab::{PrefabLibrary, DEFAULT_PREFABS};
+pub use wfc::WfcGenerator;
+
+// A group of floor tiles that can be populated together, such as a BSP room
+// or a patch of cave.
+pub struct SpawnRegion {
+    pub tiles: Vec<Position>
+}
+
+// Where a generated level wants things spawned.
+#[derive(Default)]
+pub struct Layout {
+    pub regions: Vec<SpawnRegion>,
+    // Tiles a prefab asked to have a monster on.
+    pub monsters: Vec<Position>,
+    pub treasure: Vec<Position>
+}
+impl From<Vec<SpawnRegion>> for Layout {
+    fn from(regions: Vec<SpawnRegion>) -> Self {
+        Self { regions, ..Self::default() }
+    }
+}
+
+// Fills a map that starts out as solid wall and returns where things may spawn.
+// Every generator must leave both stairs on the map, reachable from each other.
+pub trait MapGenerator {
+    fn generate(&self, map: &mut Map, rng: &mut StdRng) -> Layout;
+}
+
+pub struct BspGenerator {
+    pub depth: isize,
+    pub corridors: Corridors,
+    pub prefabs: Rc<PrefabLibrary>,
+    // The dungeon level being generated, which decides the prefabs allowed.
+    pub level: usize,
+    pub prefab_chance: f64
+}
+impl MapGenerator for BspGenerator {
+    fn generate(&self, map: &mut Map, rng: &mut StdRng) -> Layout {
+        let rooms = BSPNode::create_dungeon(map, self.depth, &self.corridors, rng);
+        let mut layout = Layout::default();
+        // The first and last rooms hold the stairs, so they are left plain.
+        for room in rooms.iter().skip(1).take(rooms.len().saturating_sub(2)) {
+            if rng.random_bool(self.prefab_chance) {
+                self.prefabs.stamp(map, room.interior_bounds(), self.level, rng, &mut layout);
+            }
+        }
+        layout.regions = rooms.iter()
+            .map(|room| SpawnRegion {
+                tiles: room.interior()
+                    .filter(|(x, y)| map.tile_at(*x, *y) == Some(Tile::Floor))
+                    .map(|(x, y)| Position::new(x, y))
+                    .filter(|pos| !layout.treasure.contains(pos))
+                    .collect()
+            })
+            .collect();
+        layout
+    }
+}
+
+// Runs `generator`, then makes sure every walkable tile can be reached from the
+// up stairs, where the player arrives, digging corridors to any it missed.
+pub fn generate_level(generator: &dyn MapGenerator, map: &mut Map, rng: &mut StdRng) -> Layout {
+    let layout = generator.generate(map, rng);
+    if let Some((x, y)) = map.find_tile(Tile::StairsUp) {
+        repair_connectivity(map, &Position::new(x, y));
+    }
+    layout
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GeneratorKind {
+    Bsp,
+    Caves,
+    Drunkard,
+    Dla(DlaMode),
+    Wfc
+}
+impl GeneratorKind {
+    pub fn build(self, config: &GameConfig, level: usize) -> Box<dyn MapGenerator> {
+        match self {
+            GeneratorKind::Bsp => Box::new(BspGenerator {
+                depth: config.bsp_depth,
+                corridors: config.corridors,
+                prefabs: Rc::clone(&config.prefabs),
+                level,
+                prefab_chance: 0.4
+            }),
+            GeneratorKind::Caves => Box::new(CellularAutomataGenerator::default()),
+            GeneratorKind::Drunkard => Box::new(DrunkardsWalkGenerator::default()),
+            GeneratorKind::Dla(mode) => Box::new(DlaGenerator { mode, ..DlaGenerator::default() }),
+            GeneratorKind::Wfc => Box::new(WfcGenerator::default())
+        }
+    }
+
+    // Level `depth` uses the matching entry, and the last entry repeats for deeper levels.
+    pub fn for_depth(kinds: &[GeneratorKind], depth: usize) -> GeneratorKind {
+        kinds.get(depth)
+            .or(kinds.last())
+            .copied()
+            .unwrap_or(GeneratorKind::Bsp)
+    }
+}
+impl FromStr for GeneratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bsp" => Ok(GeneratorKind::Bsp),
+            "caves" => Ok(GeneratorKind::Caves),
+            "drunkard" => Ok(GeneratorKind::Drunkard),
+            "dla-inward" => Ok(GeneratorKind::Dla(DlaMode::WalkInwards)),
+            "dla-outward" => Ok(GeneratorKind::Dla(DlaMode::WalkOutwards)),
+            "dla-attractor" => Ok(GeneratorKind::Dla(DlaMode::CentralAttractor)),
+            "wfc" => Ok(GeneratorKind::Wfc),
+            _ => Err(format!("unknown generator: {s}"))
+        }
+    }
+}
+
+fn floor_tiles(map: &Map) -> Vec<Position> {
+    map.get_tiles()
+        .iter()
+        .enumerate()
+        .filter(|(_, tile)| **tile == Tile::Floor)
+        .map(|(idx, _)| {
+            let (y, x) = map.idx_xy(idx);
+            Position::new(x, y)
+        })
+        .collect()
+}
+
+// Groups floor tiles into pockets that can reach each other, largest first.
+fn floor_regions(map: &Map) -> Vec<Vec<Position>> {
+    let mut seen = vec![false; map.rows() * map.columns()];
+    let mut regions: Vec<Vec<Position>> = vec![];
+    for pos in floor_tiles(map) {
+        if seen[map.xy_idx(pos.x, pos.y)] {
+            continue;
+        }
+        let mut region = vec![];
+        for (idx, distance) in distance_map(map, &pos).iter().enumerate() {
+            if distance.is_some() {
+                seen[idx] = true;
+                if map.get_tile(idx) == Some(Tile::Floor) {
+                    let (y, x) = map.idx_xy(idx);
+                    region.push(Position::new(x, y));
+                }
+            }
+        }
+        regions.push(region);
+    }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions
+}
+
+// Walls off every floor tile that is not connected to the largest open region.
+fn cull_disconnected(map: &mut Map) {
+    for region in floor_regions(map).iter().skip(1) {
+        for pos in region {
+            map.set_tile(pos.x, pos.y, Tile::Wall);
+        }
+    }
+}
+
+// Digs an L-shaped corridor from every smaller pocket to the closest tile of the
+// largest one, so nothing the generator made is thrown away.
+fn connect_disconnected(map: &mut Map) {
+    let regions = floor_regions(map);
+    let Some((main, others)) = regions.split_first() else {
+        return;
+    };
+    for region in others {
+        dig_between(map, region, main);
+    }
+}
+
+// Carves an L-shaped corridor between the closest pair of tiles from `from` and `to`.
+fn dig_between(map: &mut Map, from: &[Position], to: &[Position]) {
+    let Some((from, to)) = from.iter()
+        .flat_map(|from| to.iter().map(move |to| (from, to)))
+        .min_by_key(|(from, to)| manhattan_distance(from, to)) else {
+        return;
+    };
+    for (x, y) in corridors::l_path((from.x, from.y), (to.x, to.y)) {
+        map.carve_corridor(x, y);
+    }
+}
+
+// Puts the up stairs on a random floor tile and the down stairs on the floor
+// tile farthest from it, so organic levels still have to be crossed.
+fn place_stairs_apart(map: &mut Map, rng: &mut StdRng) {
+    let floors = floor_tiles(map);
+    if floors.len() < 2 {
+        return;
+    }
+    let up = floors[rng.random_range(0..floors.len())].clone();
+    let distances = distance_map(map, &up);
+    let down = floors.iter()
+        .filter(|pos| **pos != up)
+        .max_by_key(|pos| distances[map.xy_idx(pos.x, pos.y)])
+        .expect("There are at least two floor tiles")
+        .clone();
+    map.set_tile(up.x, up.y, Tile::StairsUp);
+    map.set_tile(down.x, down.y, Tile::StairsDown);
+}
+
+fn floor_percent(map: &Map) -> usize {
+    floor_tiles(map).len() * 100 / (map.rows() * map.columns())
+}
+
+// Splits the floor into `count` patches by assigning every tile to its nearest
+// randomly chosen seed tile, for maps without rooms to spawn into.
+fn voronoi_regions(map: &Map, count: usize, rng: &mut StdRng) -> Vec<SpawnRegion> {
+    let floors = floor_tiles(map);
+    if floors.is_empty() {
+        return vec![];
+    }
+    let seeds: Vec<Position> = (0..count.max(1))
+        .map(|_| floors[rng.random_range(0..floors.len())].clone())
+        .collect();
+    let mut regions: Vec<SpawnRegion> = seeds.iter()
+        .map(|_| SpawnRegion { tiles: vec![] })
+        .collect();
+    for pos in floors {
+        let nearest = (0..seeds.len())
+            .min_by_key(|seed| manhattan_distance(&seeds[*seed], &pos))
+            .expect("There is at least one seed");
+        regions[nearest].tiles.push(pos);
+    }
+    regions.retain(|region| !region.tiles.is_empty());
+    regions
+}
+
+// One step in a random cardinal direction, kept off the map's outer edge.
+fn random_step(map: &Map, pos: &Position, rng: &mut StdRng) -> Position {
+    let (dx, dy) = [(0, -1), (-1, 0), (0, 1), (1, 0)][rng.random_range(0..4)];
+    let x = pos.x.saturating_add_signed(dx).clamp(1, map.columns().saturating_sub(2).max(1));
+    let y = pos.y.saturating_add_signed(dy).clamp(1, map.rows().saturating_sub(2).max(1));
+    Position::new(x, y)
+}
+
+fn map_centre(map: &Map) -> Position {
+    Position::new(map.columns() / 2, map.rows() / 2)
+}
@@ -0,0 +1,288 @@
+use std::{fs, ops::RangeInclusive, path::Path};
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::{components::Position, map::Map, tile::Tile};
+
+use super::Layout;
+
+pub const DEFAULT_PREFABS: &str = include_str!("../../data/prefabs.txt");
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cell {
+    // A space in the layout leaves whatever the room already had.
+    Keep,
+    Wall,
+    Floor,
+    Door,
+    Monster,
+    Treasure
+}
+impl Cell {
+    fn parse(glyph: char) -> Option<Self> {
+        match glyph {
+            ' ' => Some(Cell::Keep),
+            '#' => Some(Cell::Wall),
+            '.' => Some(Cell::Floor),
+            '+' => Some(Cell::Door),
+            'g' => Some(Cell::Monster),
+            '$' => Some(Cell::Treasure),
+            _ => None
+        }
+    }
+}
+
+struct Prefab {
+    weight: usize,
+    depth: RangeInclusive<usize>,
+    // Every orientation the prefab may be stamped in, the original first.
+    variants: Vec<Vec<Vec<Cell>>>
+}
+
+// Hand-drawn rooms and vaults. Each entry starts with `=== name`, followed by
+// optional `key: value` lines and then the layout itself. Lines starting with
+// `;` are comments.
+//
+//   weight: 3          how often it is picked relative to the others (default 1)
+//   depth: 2-5         levels it may appear on; `4+` means 4 and deeper (default all)
+//   transform: rotate mirror    orientations it may be stamped in (default none)
+//
+// Layout legend: `#` wall, `.` floor, `+` door, `g` monster, `$` treasure, and a
+// space keeps the room's own tile. Every row must be as wide as the first, so
+// keep trailing spaces.
+#[derive(Default)]
+pub struct PrefabLibrary {
+    prefabs: Vec<Prefab>
+}
+impl PrefabLibrary {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&source).map_err(|e| format!("{}:{e}", path.display()))
+    }
+
+    // Errors are prefixed with the 1-based line number they refer to.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut prefabs = vec![];
+        let mut lines = source.lines().enumerate().map(|(idx, line)| (idx + 1, line)).peekable();
+        while let Some((number, line)) = lines.next() {
+            if line.trim().is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let Some(name) = line.strip_prefix("===") else {
+                return Err(format!("{number}: expected `=== name` to start a prefab"));
+            };
+            let name = name.trim();
+            let mut weight = 1;
+            let mut depth = 0..=usize::MAX;
+            let (mut rotate, mut mirror) = (false, false);
+            let mut rows: Vec<Vec<Cell>> = vec![];
+            while let Some((number, line)) = lines.next_if(|(_, line)| !line.starts_with("===")) {
+                if line.starts_with(';') || (line.trim().is_empty() && rows.is_empty()) {
+                    continue;
+                }
+                if rows.is_empty() && let Some((key, value)) = line.split_once(':') {
+                    let value = value.trim();
+                    match key.trim() {
+                        "weight" => weight = value.parse()
+                            .map_err(|_| format!("{number}: {name}: invalid weight `{value}`"))?,
+                        "depth" => depth = Self::parse_depth(value)
+                            .ok_or(format!("{number}: {name}: invalid depth `{value}`"))?,
+                        "transform" => for transform in value.split_whitespace() {
+                            match transform {
+                                "rotate" => rotate = true,
+                                "mirror" => mirror = true,
+                                "none" => (),
+                                _ => return Err(format!("{number}: {name}: unknown transform `{transform}`"))
+                            }
+                        },
+                        key => return Err(format!("{number}: {name}: unknown key `{key}`"))
+                    }
+                    continue;
+                }
+                if line.trim().is_empty() {
+                    break;
+                }
+                let row = line.chars()
+                    .enumerate()
+                    .map(|(column, glyph)| Cell::parse(glyph)
+                        .ok_or(format!("{number}:{}: {name}: unknown glyph `{glyph}`", column + 1)))
+                    .collect::<Result<Vec<Cell>, String>>()?;
+                if let Some(first) = rows.first()
+                    && first.len() != row.len() {
+                    return Err(format!("{number}: {name}: row is {} wide but the first row is {}", row.len(), first.len()));
+                }
+                rows.push(row);
+            }
+            if rows.is_empty() {
+                return Err(format!("{number}: {name}: prefab has no layout"));
+            }
+            if weight == 0 {
+                return Err(format!("{number}: {name}: weight must be at least 1"));
+            }
+            prefabs.push(Prefab { weight, depth, variants: Self::variants(rows, rotate, mirror) });
+        }
+        Ok(Self { prefabs })
+    }
+
+    fn parse_depth(value: &str) -> Option<RangeInclusive<usize>> {
+        if let Some(min) = value.strip_suffix('+') {
+            return Some(min.trim().parse().ok()?..=usize::MAX);
+        }
+        match value.split_once('-') {
+            Some((min, max)) => {
+                let (min, max) = (min.trim().parse().ok()?, max.trim().parse().ok()?);
+                (min <= max).then_some(min..=max)
+            },
+            None => {
+                let depth = value.parse().ok()?;
+                Some(depth..=depth)
+            }
+        }
+    }
+
+    // A quarter turn clockwise.
+    fn rotated(grid: &[Vec<Cell>]) -> Vec<Vec<Cell>> {
+        (0..grid[0].len())
+            .map(|x| (0..grid.len()).rev().map(|y| grid[y][x]).collect())
+            .collect()
+    }
+
+    // Flipped left to right.
+    fn mirrored(grid: &[Vec<Cell>]) -> Vec<Vec<Cell>> {
+        grid.iter().map(|row| row.iter().rev().copied().collect()).collect()
+    }
+
+    fn variants(layout: Vec<Vec<Cell>>, rotate: bool, mirror: bool) -> Vec<Vec<Vec<Cell>>> {
+        let mut variants = vec![layout];
+        if rotate {
+            for _ in 0..3 {
+                let next = Self::rotated(variants.last().expect("Variants are never empty"));
+                variants.push(next);
+            }
+        }
+        if mirror {
+            let mirrored: Vec<Vec<Vec<Cell>>> = variants.iter()
+                .map(|grid| Self::mirrored(grid))
+                .collect();
+            variants.extend(mirrored);
+        }
+        variants
+    }
+
+    // Picks a prefab allowed at `depth` by weight, in an orientation that fits in
+    // a `width` x `height` area.
+    fn pick(&self, width: usize, height: usize, depth: usize, rng: &mut StdRng) -> Option<&Vec<Vec<Cell>>> {
+        let candidates: Vec<(&Prefab, Vec<&Vec<Vec<Cell>>>)> = self.prefabs.iter()
+            .filter(|prefab| prefab.depth.contains(&depth))
+            .map(|prefab| (prefab, prefab.variants.iter()
+                .filter(|grid| grid.len() <= height && grid[0].len() <= width)
+                .collect::<Vec<_>>()))
+            .filter(|(_, fitting)| !fitting.is_empty())
+            .collect();
+        let total: usize = candidates.iter().map(|(prefab, _)| prefab.weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.random_range(0..total);
+        for (prefab, fitting) in candidates {
+            if roll < prefab.weight {
+                return Some(fitting[rng.random_range(0..fitting.len())]);
+            }
+            roll -= prefab.weight;
+        }
+        None
+    }
+
+    // Stamps a fitting prefab into the middle of the area at (`x`, `y`), leaving a
+    // one tile ring of the room around it so corridors still reach every side.
+    // Spawn markers are added to `layout`. Returns false if nothing fits.
+    pub fn stamp(&self, map: &mut Map, area: (usize, usize, usize, usize), depth: usize, rng: &mut StdRng, layout: &mut Layout) -> bool {
+        let (x, y, width, height) = area;
+        let (Some(width), Some(height)) = (width.checked_sub(2), height.checked_sub(2)) else {
+            return false;
+        };
+        let Some(grid) = self.pick(width, height, depth, rng) else {
+            return false;
+        };
+        let (width, height) = (width + 2, height + 2);
+        let left = x + (width - grid[0].len()) / 2;
+        let top = y + (height - grid.len()) / 2;
+        for (dy, row) in grid.iter().enumerate() {
+            for (dx, cell) in row.iter().enumerate() {
+                let (x, y) = (left + dx, top + dy);
+                let tile = match cell {
+                    Cell::Keep => continue,
+                    Cell::Wall => Tile::Wall,
+                    Cell::Door => Tile::DoorClosed,
+                    Cell::Floor => Tile::Floor,
+                    Cell::Monster => {
+                        layout.monsters.push(Position::new(x, y));
+                        Tile::Floor
+                    },
+                    Cell::Treasure => {
+                        layout.treasure.push(Position::new(x, y));
+                        Tile::Floor
+                    }
+                };
+                map.set_tile(x, y, tile);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lopsided on purpose, so any rotation or flip of it looks different.
+    const SOURCE: &str = "\
+=== corner
+transform: rotate mirror
+##+.
+#g..
+#...
+";
+
+    fn layout(library: &PrefabLibrary) -> &Vec<Vec<Cell>> {
+        &library.prefabs[0].variants[0]
+    }
+
+    #[test]
+    fn parses_every_orientation() {
+        let library = PrefabLibrary::parse(SOURCE).expect("Prefab should parse");
+        let variants = &library.prefabs[0].variants;
+        assert_eq!(variants.len(), 8);
+        assert_eq!((layout(&library).len(), layout(&library)[0].len()), (3, 4));
+        assert!(variants[1].len() == 4 && variants[1][0].len() == 3);
+        PrefabLibrary::parse(DEFAULT_PREFABS).expect("The default prefabs should parse");
+    }
+
+    #[test]
+    fn rejects_ragged_rows() {
+        let Err(e) = PrefabLibrary::parse("=== ragged\n###\n#.#.\n###\n") else {
+            panic!("Ragged prefab should not parse");
+        };
+        assert_eq!(e, "3: ragged: row is 4 wide but the first row is 3");
+    }
+
+    #[test]
+    fn four_rotations_come_back_round() {
+        let library = PrefabLibrary::parse(SOURCE).expect("Prefab should parse");
+        let original = layout(&library);
+        let mut grid = original.clone();
+        for turn in 1..=4 {
+            grid = PrefabLibrary::rotated(&grid);
+            assert_eq!(grid == *original, turn == 4, "after {turn} turns");
+        }
+    }
+
+    #[test]
+    fn mirroring_twice_comes_back_round() {
+        let library = PrefabLibrary::parse(SOURCE).expect("Prefab should parse");
+        let original = layout(&library);
+        let mirrored = PrefabLibrary::mirrored(original);
+        assert!(mirrored != *original);
+        assert!(PrefabLibrary::mirrored(&mirrored) == *original);
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::{map::Map, tile::Tile};
+
+use super::{connect_disconnected, floor_percent, place_stairs_apart, voronoi_regions, CellularAutomataGenerator, Layout, MapGenerator};
+
+const DEFAULT_SAMPLE: &str = "\
+.....#..........
+.....#..........
+...........#....
+.....#.....#....
+######.#####....
+.......#........
+.####..#.#######
+.#..#...........
+.#..######.###..
+................";
+
+// Up, right, down, left.
+const DIRECTIONS: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+// Overlapping-model wave function collapse. Every NxN window of the sample (and its
+// rotations and mirrors) becomes a pattern, and the output is built so that every
+// NxN window of it is one of those patterns.
+pub struct WfcGenerator {
+    patterns: Vec<Vec<bool>>,
+    weights: Vec<usize>,
+    // compatible[dir][p] lists the patterns that may sit one step from `p` in `dir`.
+    compatible: [Vec<Vec<usize>>; 4],
+    size: usize,
+    pub max_attempts: usize,
+    pub max_backtracks: usize,
+    pub min_floor_percent: usize,
+    pub spawn_regions: usize
+}
+impl Default for WfcGenerator {
+    fn default() -> Self {
+        Self::from_sample(DEFAULT_SAMPLE, 3).expect("The built-in sample should be valid")
+    }
+}
+impl WfcGenerator {
+    // `sample` uses the map alphabet: '#' for wall and '.' for floor. It is treated as
+    // wrapping around at the edges so every tile starts an NxN window.
+    pub fn from_sample(sample: &str, size: usize) -> Result<Self, String> {
+        let rows: Vec<&str> = sample.lines().filter(|line| !line.is_empty()).collect();
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        if size == 0 || rows.len() < size || width < size {
+            return Err(format!("sample must be at least {size}x{size}"));
+        }
+        let mut grid = vec![];
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(format!("sample line {} is {} wide, expected {width}", y + 1, row.chars().count()));
+            }
+            for (x, glyph) in row.chars().enumerate() {
+                grid.push(match glyph {
+                    '#' => true,
+                    '.' => false,
+                    _ => return Err(format!("sample line {}, column {}: unexpected '{glyph}'", y + 1, x + 1))
+                });
+            }
+        }
+        let mut counts: HashMap<Vec<bool>, usize> = HashMap::new();
+        let mut order: Vec<Vec<bool>> = vec![];
+        for y in 0..rows.len() {
+            for x in 0..width {
+                let window: Vec<bool> = (0..size * size)
+                    .map(|i| grid[((y + i / size) % rows.len()) * width + (x + i % size) % width])
+                    .collect();
+                for pattern in Self::symmetries(&window, size) {
+                    let count = counts.entry(pattern.clone()).or_insert(0);
+                    if *count == 0 {
+                        order.push(pattern);
+                    }
+                    *count += 1;
+                }
+            }
+        }
+        let weights = order.iter().map(|pattern| counts[pattern]).collect();
+        let compatible = DIRECTIONS.map(|(dx, dy)| {
+            order.iter()
+                .map(|p| (0..order.len()).filter(|q| Self::agrees(p, &order[*q], dx, dy, size)).collect())
+                .collect()
+        });
+        Ok(Self {
+            patterns: order,
+            weights,
+            compatible,
+            size,
+            max_attempts: 5,
+            max_backtracks: 2000,
+            min_floor_percent: 25,
+            spawn_regions: 8
+        })
+    }
+
+    fn symmetries(window: &[bool], size: usize) -> Vec<Vec<bool>> {
+        let rotate = |p: &[bool]| -> Vec<bool> {
+            (0..size * size).map(|i| p[(size - 1 - i % size) * size + i / size]).collect()
+        };
+        let mirror = |p: &[bool]| -> Vec<bool> {
+            (0..size * size).map(|i| p[(i / size) * size + size - 1 - i % size]).collect()
+        };
+        let mut variants = vec![window.to_vec()];
+        for _ in 0..3 {
+            let next = rotate(variants.last().expect("Variants are never empty"));
+            variants.push(next);
+        }
+        let mirrored: Vec<Vec<bool>> = variants.iter().map(|p| mirror(p)).collect();
+        variants.extend(mirrored);
+        variants
+    }
+
+    // Whether `q`, placed at offset (dx, dy) from `p`, matches `p` where they overlap.
+    fn agrees(p: &[bool], q: &[bool], dx: isize, dy: isize, size: usize) -> bool {
+        let size = size as isize;
+        (0..size).all(|y| (0..size).all(|x| {
+            let (qx, qy) = (x - dx, y - dy);
+            !(0..size).contains(&qx) || !(0..size).contains(&qy)
+                || p[(y * size + x) as usize] == q[(qy * size + qx) as usize]
+        }))
+    }
+
+    fn synthesize(&self, width: usize, height: usize, rng: &mut StdRng) -> Option<Vec<bool>> {
+        let wave_width = width.checked_sub(self.size)? + 1;
+        let wave_height = height.checked_sub(self.size)? + 1;
+        let mut wave = Wave::new(wave_width, wave_height, &self.compatible);
+        let mut decisions: Vec<(usize, usize, usize)> = vec![];
+        let mut backtracks = 0;
+        let mut consistent = true;
+        loop {
+            if consistent {
+                let Some(cell) = wave.lowest_entropy(rng) else {
+                    break;
+                };
+                let pattern = self.choose(&wave, cell, rng);
+                decisions.push((wave.trail.len(), cell, pattern));
+                let others: Vec<usize> = wave.options(cell).filter(|other| *other != pattern).collect();
+                consistent = others.into_iter().all(|other| wave.ban(cell, other, &self.compatible));
+                continue;
+            }
+            // Contradiction: undo the latest choice and rule it out instead.
+            backtracks += 1;
+            let (mark, cell, pattern) = decisions.pop()?;
+            if backtracks > self.max_backtracks {
+                return None;
+            }
+            wave.undo(mark, &self.compatible);
+            consistent = wave.ban(cell, pattern, &self.compatible);
+        }
+        let mut walls = vec![true; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let (cx, cy) = (x.min(wave_width - 1), y.min(wave_height - 1));
+                let pattern = wave.options(cy * wave_width + cx).next()?;
+                walls[y * width + x] = self.patterns[pattern][(y - cy) * self.size + (x - cx)];
+            }
+        }
+        Some(walls)
+    }
+
+    fn choose(&self, wave: &Wave, cell: usize, rng: &mut StdRng) -> usize {
+        let options: Vec<usize> = wave.options(cell).collect();
+        let total: usize = options.iter().map(|p| self.weights[*p]).sum();
+        let mut roll = rng.random_range(0..total);
+        for p in &options {
+            if roll < self.weights[*p] {
+                return *p;
+            }
+            roll -= self.weights[*p];
+        }
+        *options.last().expect("Cells are never chosen once empty")
+    }
+}
+impl MapGenerator for WfcGenerator {
+    fn generate(&self, map: &mut Map, rng: &mut StdRng) -> Layout {
+        let (width, height) = (map.columns(), map.rows());
+        for _ in 0..self.max_attempts {
+            let Some(walls) = self.synthesize(width, height, rng) else {
+                continue;
+            };
+            for y in 0..height {
+                for x in 0..width {
+                    let border = x == 0 || y == 0 || x + 1 == width || y + 1 == height;
+                    let wall = border || walls[y * width + x];
+                    map.set_tile(x, y, if wall { Tile::Wall } else { Tile::Floor });
+                }
+            }
+            connect_disconnected(map);
+            if floor_percent(map) >= self.min_floor_percent {
+                place_stairs_apart(map, rng);
+                return voronoi_regions(map, self.spawn_regions, rng).into();
+            }
+        }
+        // The sample could not be tiled at this size; caves keep the level playable.
+        for idx in 0..width * height {
+            let (y, x) = map.idx_xy(idx);
+            map.set_tile(x, y, Tile::Wall);
+        }
+        CellularAutomataGenerator::default().generate(map, rng)
+    }
+}
+
+// The set of patterns still possible in every output cell. `support` counts, per
+// cell, direction and pattern, how many patterns in the neighbour behind that
+// direction still allow it; a pattern whose count hits zero can be ruled out.
+// Every ban is recorded on a trail so a failed choice can be rolled back.
+struct Wave {
+    width: usize,
+    height: usize,
+    patterns: usize,
+    possible: Vec<bool>,
+    remaining: Vec<usize>,
+    support: Vec<u32>,
+    trail: Vec<(usize, usize)>
+}
+impl Wave {
+    fn new(width: usize, height: usize, compatible: &[Vec<Vec<usize>>; 4]) -> Self {
+        let patterns = compatible[0].len();
+        let mut initial = vec![0u32; 4 * patterns];
+        for (dir, lists) in compatible.iter().enumerate() {
+            for list in lists {
+                for q in list {
+                    initial[dir * patterns + q] += 1;
+                }
+            }
+        }
+        Self {
+            width,
+            height,
+            patterns,
+            possible: vec![true; width * height * patterns],
+            remaining: vec![patterns; width * height],
+            support: initial.repeat(width * height),
+            trail: vec![]
+        }
+    }
+
+    fn options(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.patterns).filter(move |pattern| self.possible[cell * self.patterns + pattern])
+    }
+
+    fn neighbour(&self, cell: usize, dir: usize) -> Option<usize> {
+        let (dx, dy) = DIRECTIONS[dir];
+        let x = (cell % self.width).checked_add_signed(dx).filter(|x| *x < self.width)?;
+        let y = (cell / self.width).checked_add_signed(dy).filter(|y| *y < self.height)?;
+        Some(y * self.width + x)
+    }
+
+    // Rules `pattern` out of `cell` and propagates whatever that rules out in turn.
+    // Returns false on a contradiction.
+    fn ban(&mut self, cell: usize, pattern: usize, compatible: &[Vec<Vec<usize>>; 4]) -> bool {
+        let mut pending = vec![(cell, pattern)];
+        while let Some((cell, pattern)) = pending.pop() {
+            if !self.possible[cell * self.patterns + pattern] {
+                continue;
+            }
+            self.possible[cell * self.patterns + pattern] = false;
+            self.remaining[cell] -= 1;
+            self.trail.push((cell, pattern));
+            for (dir, lists) in compatible.iter().enumerate() {
+                let Some(neighbour) = self.neighbour(cell, dir) else {
+                    continue;
+                };
+                for q in &lists[pattern] {
+                    let support = &mut self.support[(neighbour * 4 + dir) * self.patterns + q];
+                    *support -= 1;
+                    if *support == 0 {
+                        pending.push((neighbour, *q));
+                    }
+                }
+            }
+            if self.remaining[cell] == 0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn undo(&mut self, mark: usize, compatible: &[Vec<Vec<usize>>; 4]) {
+        while self.trail.len() > mark {
+            let (cell, pattern) = self.trail.pop().expect("Trail is longer than the mark");
+            self.possible[cell * self.patterns + pattern] = true;
+            self.remaining[cell] += 1;
+            for (dir, lists) in compatible.iter().enumerate() {
+                if let Some(neighbour) = self.neighbour(cell, dir) {
+                    for q in &lists[pattern] {
+                        self.support[(neighbour * 4 + dir) * self.patterns + q] += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    // The undecided cell with the fewest options left, ties broken at random.
+    fn lowest_entropy(&self, rng: &mut StdRng) -> Option<usize> {
+        let fewest = self.remaining.iter().filter(|count| **count > 1).min()?;
+        let candidates: Vec<usize> = (0..self.remaining.len())
+            .filter(|cell| self.remaining[*cell] == *fewest)
+            .collect();
+        Some(candidates[rng.random_range(0..candidates.len())])
+    }
+}
@@ -0,0 +1,83 @@
+use std::{cmp::Reverse, collections::{BinaryHeap, HashMap, VecDeque}};
+
+use crate::{components::Position, map::Map};
+
+pub fn manhattan_distance(a: &Position, b: &Position) -> usize {
+    a.x.abs_diff(b.x) + a.y.abs_diff(b.y)
+}
+
+pub fn neighbours(map: &Map, pos: &Position) -> Vec<Position> {
+    [(0, -1), (-1, 0), (0, 1), (1, 0)]
+        .into_iter()
+        .filter_map(|(dx, dy)| Some(Position::new(pos.x.checked_add_signed(dx)?, pos.y.checked_add_signed(dy)?)))
+        .filter(|step| map.is_passable(step.x, step.y))
+        .collect()
+}
+
+// A* over the map's grid, where `cost` prices stepping onto a tile and `None`
+// rules it out. Costs should be at least 1 for the distance estimate to hold.
+// The path includes both `start` and `goal`.
+pub fn a_star_by(map: &Map, start: &Position, goal: &Position, cost_of: impl Fn(&Position) -> Option<usize>) -> Option<Vec<Position>> {
+    let start_idx = map.xy_idx(start.x, start.y);
+    let goal_idx = map.xy_idx(goal.x, goal.y);
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<usize, usize> = HashMap::new();
+    let mut cost: HashMap<usize, usize> = HashMap::from([(start_idx, 0)]);
+    open.push(Reverse((manhattan_distance(start, goal), start_idx)));
+    while let Some(Reverse((_, idx))) = open.pop() {
+        if idx == goal_idx {
+            let mut path = vec![idx];
+            while let Some(previous) = came_from.get(path.last().expect("Path is never empty")) {
+                path.push(*previous);
+            }
+            return Some(path.into_iter()
+                .rev()
+                .map(|idx| {
+                    let (y, x) = map.idx_xy(idx);
+                    Position::new(x, y)
+                })
+                .collect());
+        }
+        let (y, x) = map.idx_xy(idx);
+        let steps = [(0, -1), (-1, 0), (0, 1), (1, 0)]
+            .into_iter()
+            .filter_map(|(dx, dy)| Some(Position::new(x.checked_add_signed(dx)?, y.checked_add_signed(dy)?)))
+            .filter(|step| map.in_bounds(step.x, step.y));
+        for next in steps {
+            let Some(tile_cost) = cost_of(&next) else {
+                continue;
+            };
+            let next_idx = map.xy_idx(next.x, next.y);
+            let step_cost = cost[&idx] + tile_cost;
+            if cost.get(&next_idx).is_some_and(|known| *known <= step_cost) {
+                continue;
+            }
+            cost.insert(next_idx, step_cost);
+            came_from.insert(next_idx, idx);
+            open.push(Reverse((step_cost + manhattan_distance(&next, goal), next_idx)));
+        }
+    }
+    None
+}
+
+// Breadth-first step counts from `start` to every passable tile, indexed like the map.
+// Unreachable tiles are `None`.
+pub fn distance_map(map: &Map, start: &Position) -> Vec<Option<usize>> {
+    let mut distances = vec![None; map.rows() * map.columns()];
+    if !map.is_passable(start.x, start.y) {
+        return distances;
+    }
+    distances[map.xy_idx(start.x, start.y)] = Some(0);
+    let mut frontier = VecDeque::from([start.clone()]);
+    while let Some(pos) = frontier.pop_front() {
+        let distance = distances[map.xy_idx(pos.x, pos.y)].expect("Frontier tiles are reached");
+        for next in neighbours(map, &pos) {
+            let idx = map.xy_idx(next.x, next.y);
+            if distances[idx].is_none() {
+                distances[idx] = Some(distance + 1);
+                frontier.push_back(next);
+            }
+        }
+    }
+    distances
+}
@@ -0,0 +1,350 @@
+use std::{any::{type_name, TypeId}, marker::PhantomData, ptr::NonNull};
+
+use crate::{storage::{ArchetypeKey, Table}, world::Entity};
+
+#[derive(Default)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>
+}
+impl Access {
+    pub fn read<T: 'static>(&mut self) {
+        let id = TypeId::of::<T>();
+        assert!(!self.writes.contains(&id), "{} is already borrowed mutably in this query", type_name::<T>());
+        self.reads.push(id);
+    }
+
+    pub fn write<T: 'static>(&mut self) {
+        let id = TypeId::of::<T>();
+        assert!(
+            !self.writes.contains(&id) && !self.reads.contains(&id),
+            "{} is already borrowed in this query", type_name::<T>()
+        );
+        self.writes.push(id);
+    }
+
+    pub fn writes<T: 'static>(&self) -> bool {
+        self.writes.contains(&TypeId::of::<T>())
+    }
+}
+
+/// # Safety
+/// Implementors must only touch the columns they declare in `access`, and only
+/// mutably if they declared a write, so rows handed out by `QueryIter` never alias.
+pub unsafe trait QueryData {
+    type Item<'w>;
+    type Fetch<'w>: Copy;
+
+    fn matches(key: &ArchetypeKey) -> bool;
+    fn access(access: &mut Access);
+
+    /// # Safety
+    /// `table` must match this query and stay borrowed (mutably, unless the query
+    /// is read-only) for `'w`.
+    unsafe fn fetch_table<'w>(table: NonNull<Table>) -> Self::Fetch<'w>;
+
+    /// # Safety
+    /// `row` must be in bounds and yielded at most once per fetch.
+    unsafe fn fetch<'w>(fetch: Self::Fetch<'w>, row: usize) -> Self::Item<'w>;
+}
+
+/// # Safety
+/// Implementors must never hand out mutable access to a column.
+pub unsafe trait ReadOnlyQueryData: QueryData {}
+
+unsafe impl<T: 'static> QueryData for &T {
+    type Item<'w> = &'w T;
+    type Fetch<'w> = NonNull<T>;
+
+    fn matches(key: &ArchetypeKey) -> bool {
+        key.contains(TypeId::of::<T>())
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<T>();
+    }
+
+    unsafe fn fetch_table<'w>(table: NonNull<Table>) -> Self::Fetch<'w> {
+        let column = unsafe { table.as_ref() }.column::<T>().expect("Table should match the query");
+        NonNull::from(column).cast()
+    }
+
+    unsafe fn fetch<'w>(fetch: Self::Fetch<'w>, row: usize) -> Self::Item<'w> {
+        unsafe { fetch.add(row).as_ref() }
+    }
+}
+unsafe impl<T: 'static> ReadOnlyQueryData for &T {}
+
+unsafe impl<T: 'static> QueryData for &mut T {
+    type Item<'w> = &'w mut T;
+    type Fetch<'w> = NonNull<T>;
+
+    fn matches(key: &ArchetypeKey) -> bool {
+        key.contains(TypeId::of::<T>())
+    }
+
+    fn access(access: &mut Access) {
+        access.write::<T>();
+    }
+
+    unsafe fn fetch_table<'w>(mut table: NonNull<Table>) -> Self::Fetch<'w> {
+        let column = unsafe { table.as_mut() }.column_mut::<T>().expect("Table should match the query");
+        NonNull::from(column).cast()
+    }
+
+    unsafe fn fetch<'w>(fetch: Self::Fetch<'w>, row: usize) -> Self::Item<'w> {
+        unsafe { fetch.add(row).as_mut() }
+    }
+}
+
+unsafe impl<Q: QueryData> QueryData for Option<Q> {
+    type Item<'w> = Option<Q::Item<'w>>;
+    type Fetch<'w> = Option<Q::Fetch<'w>>;
+
+    fn matches(_key: &ArchetypeKey) -> bool {
+        true
+    }
+
+    fn access(access: &mut Access) {
+        Q::access(access);
+    }
+
+    unsafe fn fetch_table<'w>(table: NonNull<Table>) -> Self::Fetch<'w> {
+        if Q::matches(unsafe { table.as_ref() }.key()) {
+            Some(unsafe { Q::fetch_table(table) })
+        } else {
+            None
+        }
+    }
+
+    unsafe fn fetch<'w>(fetch: Self::Fetch<'w>, row: usize) -> Self::Item<'w> {
+        fetch.map(|fetch| unsafe { Q::fetch(fetch, row) })
+    }
+}
+unsafe impl<Q: ReadOnlyQueryData> ReadOnlyQueryData for Option<Q> {}
+
+unsafe impl QueryData for () {
+    type Item<'w> = ();
+    type Fetch<'w> = ();
+
+    fn matches(_key: &ArchetypeKey) -> bool {
+        true
+    }
+
+    fn access(_access: &mut Access) {}
+
+    unsafe fn fetch_table<'w>(_table: NonNull<Table>) -> Self::Fetch<'w> {}
+
+    unsafe fn fetch<'w>(_fetch: Self::Fetch<'w>, _row: usize) -> Self::Item<'w> {}
+}
+unsafe impl ReadOnlyQueryData for () {}
+
+macro_rules! impl_query_data {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: QueryData),+> QueryData for ($($name,)+) {
+            type Item<'w> = ($($name::Item<'w>,)+);
+            type Fetch<'w> = ($($name::Fetch<'w>,)+);
+
+            fn matches(key: &ArchetypeKey) -> bool {
+                $($name::matches(key))&&+
+            }
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            unsafe fn fetch_table<'w>(table: NonNull<Table>) -> Self::Fetch<'w> {
+                unsafe { ($($name::fetch_table(table),)+) }
+            }
+
+            unsafe fn fetch<'w>(fetch: Self::Fetch<'w>, row: usize) -> Self::Item<'w> {
+                let ($($name,)+) = fetch;
+                unsafe { ($($name::fetch($name, row),)+) }
+            }
+        }
+        unsafe impl<$($name: ReadOnlyQueryData),+> ReadOnlyQueryData for ($($name,)+) {}
+    };
+}
+
+impl_query_data!(A);
+impl_query_data!(A, B);
+impl_query_data!(A, B, C);
+impl_query_data!(A, B, C, D);
+impl_query_data!(A, B, C, D, E);
+impl_query_data!(A, B, C, D, E, F);
+
+pub trait QueryFilter {
+    fn matches(key: &ArchetypeKey) -> bool;
+}
+
+pub struct With<T>(PhantomData<T>);
+impl<T: 'static> QueryFilter for With<T> {
+    fn matches(key: &ArchetypeKey) -> bool {
+        key.contains(TypeId::of::<T>())
+    }
+}
+
+pub struct Without<T>(PhantomData<T>);
+impl<T: 'static> QueryFilter for Without<T> {
+    fn matches(key: &ArchetypeKey) -> bool {
+        !key.contains(TypeId::of::<T>())
+    }
+}
+
+impl QueryFilter for () {
+    fn matches(_key: &ArchetypeKey) -> bool {
+        true
+    }
+}
+
+macro_rules! impl_query_filter {
+    ($($name:ident),+) => {
+        impl<$($name: QueryFilter),+> QueryFilter for ($($name,)+) {
+            fn matches(key: &ArchetypeKey) -> bool {
+                $($name::matches(key))&&+
+            }
+        }
+    };
+}
+
+impl_query_filter!(A);
+impl_query_filter!(A, B);
+impl_query_filter!(A, B, C);
+impl_query_filter!(A, B, C, D);
+
+pub struct QueryIter<'w, Q: QueryData, F: QueryFilter> {
+    tables: Vec<(&'w [Entity], Q::Fetch<'w>)>,
+    table: usize,
+    row: usize,
+    _marker: PhantomData<F>
+}
+impl<'w, Q: QueryData, F: QueryFilter> QueryIter<'w, Q, F> {
+    /// # Safety
+    /// Every table must stay borrowed for `'w`, mutably unless `Q` is read-only.
+    pub unsafe fn new(tables: impl Iterator<Item = NonNull<Table>>) -> Self {
+        Q::access(&mut Access::default());
+        let tables = tables
+            .filter(|table| {
+                let key = unsafe { table.as_ref() }.key();
+                Q::matches(key) && F::matches(key)
+            })
+            .map(|table| unsafe {
+                let fetch = Q::fetch_table(table);
+                (table.as_ref().entities(), fetch)
+            })
+            .collect();
+        Self { tables, table: 0, row: 0, _marker: PhantomData }
+    }
+}
+impl<'w, Q: QueryData, F: QueryFilter> Iterator for QueryIter<'w, Q, F> {
+    type Item = (Entity, Q::Item<'w>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (entities, fetch) = self.tables.get(self.table)?;
+            if let Some(entity) = entities.get(self.row) {
+                let item = unsafe { Q::fetch(*fetch, self.row) };
+                self.row += 1;
+                return Some((*entity, item));
+            }
+            self.table += 1;
+            self.row = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{map::Map, world::World};
+
+    use super::*;
+
+    struct A(u32);
+
+    struct B(u32);
+
+    struct Tag;
+
+    // One entity in each of four archetypes: (A), (A, B), (A, B, Tag) and (B).
+    fn world() -> (World, [Entity; 4]) {
+        let mut world = World::new(Map::new(4, 4));
+        let entities = [
+            world.spawn((A(1),)),
+            world.spawn((A(2), B(20))),
+            world.spawn((A(3), B(30), Tag)),
+            world.spawn((B(40),))
+        ];
+        (world, entities)
+    }
+
+    fn sorted<T: Ord>(mut values: Vec<T>) -> Vec<T> {
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn filters_match_archetypes() {
+        let (world, _) = world();
+        let with_b: Vec<u32> = world.query_ref::<&A, With<B>>().map(|(_, a)| a.0).collect();
+        assert_eq!(sorted(with_b), vec![2, 3]);
+        let without_b: Vec<u32> = world.query_ref::<&A, Without<B>>().map(|(_, a)| a.0).collect();
+        assert_eq!(without_b, vec![1]);
+        let untagged: Vec<u32> = world.query_ref::<&A, (With<B>, Without<Tag>)>().map(|(_, a)| a.0).collect();
+        assert_eq!(untagged, vec![2]);
+        let only_b: Vec<u32> = world.query_ref::<&B, Without<A>>().map(|(_, b)| b.0).collect();
+        assert_eq!(only_b, vec![40]);
+    }
+
+    #[test]
+    fn optional_components_are_none_where_missing() {
+        let (world, _) = world();
+        let found: Vec<(u32, Option<u32>)> = world.query_ref::<(&A, Option<&B>), ()>()
+            .map(|(_, (a, b))| (a.0, b.map(|b| b.0)))
+            .collect();
+        assert_eq!(sorted(found), vec![(1, None), (2, Some(20)), (3, Some(30))]);
+    }
+
+    #[test]
+    fn mutable_queries_write_to_every_matching_row() {
+        let (mut world, _) = world();
+        for (_, (a, b)) in world.query::<(&mut A, Option<&B>), ()>() {
+            a.0 += b.map_or(0, |b| b.0);
+        }
+        let values: Vec<u32> = world.query_ref::<&A, ()>().map(|(_, a)| a.0).collect();
+        assert_eq!(sorted(values), vec![1, 22, 33]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn reading_a_component_borrowed_mutably_panics() {
+        let (mut world, _) = world();
+        world.query::<(&mut A, &A), ()>().count();
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn borrowing_a_component_mutably_twice_panics() {
+        let (mut world, _) = world();
+        world.query::<(&mut A, &mut A), ()>().count();
+    }
+
+    #[test]
+    fn queries_follow_entities_between_tables() {
+        let (mut world, [first, second, third, fourth]) = world();
+        world.insert(first, B(10));
+        world.remove::<B>(second);
+        world.remove::<Tag>(third);
+        world.insert(fourth, A(4));
+        let found: Vec<(Entity, u32, u32)> = world.query_ref::<(&A, &B), ()>()
+            .map(|(entity, (a, b))| (entity, a.0, b.0))
+            .collect();
+        assert_eq!(found.len(), 3);
+        for (entity, a, b) in [(first, 1, 10), (third, 3, 30), (fourth, 4, 40)] {
+            assert!(found.contains(&(entity, a, b)), "{entity} should have A({a}) and B({b})");
+        }
+        let lone: Vec<(Entity, u32)> = world.query_ref::<&A, Without<B>>().map(|(entity, a)| (entity, a.0)).collect();
+        assert_eq!(lone, vec![(second, 2)]);
+        assert_eq!(world.query_ref::<(), With<Tag>>().count(), 0);
+    }
+}
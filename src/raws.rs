@@ -0,0 +1,224 @@
+use std::{fs, ops::RangeInclusive, path::Path};
+
+use crossterm::style::Color;
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{combat::DEFAULT_DEFENSE, components::{Behaviour, MonsterKind}, dice::Dice, spawn::SpawnTable};
+
+pub const DEFAULT_RAWS: &str = include_str!("../data/monsters.toml");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFile {
+    #[serde(default)]
+    monster: Vec<Spanned<RawMonster>>
+}
+
+// A `[[monster]]` table as written, before any of it is checked.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMonster {
+    name: String,
+    glyph: char,
+    colour: String,
+    hp: String,
+    strength: usize,
+    damage: String,
+    defense: Option<usize>,
+    armor: Option<usize>,
+    speed: usize,
+    ai: Option<String>,
+    sight: Option<usize>,
+    #[serde(default)]
+    flags: Vec<String>,
+    min_depth: Option<usize>,
+    max_depth: Option<usize>,
+    weight: Option<usize>
+}
+
+#[derive(Clone)]
+pub struct MonsterDef {
+    pub name: String,
+    pub glyph: char,
+    pub colour: Color,
+    pub hp: Dice,
+    pub strength: usize,
+    pub damage: Dice,
+    pub defense: usize,
+    pub armor: usize,
+    pub speed: usize,
+    pub behaviour: Behaviour,
+    pub sight: usize,
+    pub awake: bool,
+    pub stationary: bool,
+    depth: RangeInclusive<usize>,
+    weight: usize
+}
+
+// Game content loaded from data files at startup.
+pub struct Raws {
+    monsters: Vec<MonsterDef>
+}
+impl Raws {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&source).map_err(|e| format!("{}:{e}", path.display()))
+    }
+
+    // Errors are prefixed with the 1-based line number they refer to.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let line_of = |offset: usize| source[..offset.min(source.len())].matches('\n').count() + 1;
+        let file: RawFile = toml::from_str(source).map_err(|e| {
+            let line = e.span().map_or(1, |span| line_of(span.start));
+            format!("{line}: {}", e.message())
+        })?;
+        let mut monsters: Vec<MonsterDef> = vec![];
+        for raw in file.monster {
+            let line = line_of(raw.span().start);
+            let raw = raw.into_inner();
+            let name = raw.name.clone();
+            let monster = Self::validate_monster(raw).map_err(|e| format!("{line}: {name}: {e}"))?;
+            if monsters.iter().any(|other| other.name == monster.name) {
+                return Err(format!("{line}: {name}: another monster already has this name"));
+            }
+            monsters.push(monster);
+        }
+        if monsters.is_empty() {
+            return Err("1: no monsters defined".to_string());
+        }
+        Ok(Self { monsters })
+    }
+
+    fn validate_monster(raw: RawMonster) -> Result<MonsterDef, String> {
+        if raw.name.trim().is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        if raw.glyph.is_whitespace() || raw.glyph.is_control() {
+            return Err(format!("glyph {:?} would not be visible", raw.glyph));
+        }
+        let colour = Color::try_from(raw.colour.as_str())
+            .map_err(|_| format!("unknown colour `{}`", raw.colour))?;
+        let hp: Dice = raw.hp.parse()?;
+        if hp.min() == 0 {
+            return Err(format!("hp `{hp}` can roll 0"));
+        }
+        let damage: Dice = raw.damage.parse()?;
+        if damage.min() == 0 {
+            return Err(format!("damage `{damage}` can roll 0"));
+        }
+        if raw.speed == 0 {
+            return Err("speed must be at least 1".to_string());
+        }
+        let behaviour = match raw.ai.as_deref() {
+            None | Some("hunter") => Behaviour::Hunter,
+            Some("berserker") => Behaviour::Berserker,
+            Some("coward") => Behaviour::Coward,
+            Some(ai) => return Err(format!("unknown ai `{ai}`"))
+        };
+        let (mut awake, mut stationary) = (false, false);
+        for flag in &raw.flags {
+            match flag.as_str() {
+                "awake" => awake = true,
+                "stationary" => stationary = true,
+                _ => return Err(format!("unknown flag `{flag}`"))
+            }
+        }
+        let min_depth = raw.min_depth.unwrap_or(0);
+        let max_depth = raw.max_depth.unwrap_or(usize::MAX);
+        if min_depth > max_depth {
+            return Err(format!("min_depth {min_depth} is deeper than max_depth {max_depth}"));
+        }
+        let weight = raw.weight.unwrap_or(1);
+        if weight == 0 {
+            return Err("weight must be at least 1".to_string());
+        }
+        Ok(MonsterDef {
+            name: raw.name,
+            glyph: raw.glyph,
+            colour,
+            hp,
+            strength: raw.strength,
+            damage,
+            defense: raw.defense.unwrap_or(DEFAULT_DEFENSE),
+            armor: raw.armor.unwrap_or(0),
+            speed: raw.speed,
+            behaviour,
+            sight: raw.sight.unwrap_or(8),
+            awake,
+            stationary,
+            depth: min_depth..=max_depth,
+            weight
+        })
+    }
+
+    pub fn monster(&self, kind: MonsterKind) -> &MonsterDef {
+        &self.monsters[kind.0]
+    }
+
+    pub fn monster_table(&self) -> SpawnTable<MonsterKind> {
+        self.monsters.iter()
+            .enumerate()
+            .fold(SpawnTable::new(), |table, (idx, monster)| {
+                table.with(MonsterKind(idx), monster.weight, monster.depth.clone())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAT: &str = r#"
+[[monster]]
+name = "rat"
+glyph = "r"
+colour = "grey"
+hp = "1d4"
+strength = 1
+damage = "1d3"
+speed = 100
+"#;
+
+    fn error(source: &str) -> String {
+        match Raws::parse(source) {
+            Ok(_) => panic!("raws should not parse:\n{source}"),
+            Err(e) => e
+        }
+    }
+
+    #[test]
+    fn parses_a_monster() {
+        let raws = Raws::parse(RAT).expect("Raws should parse");
+        let rat = raws.monster(MonsterKind(0));
+        assert_eq!((rat.name.as_str(), rat.glyph, rat.hp), ("rat", 'r', "1d4".parse().unwrap()));
+        assert_eq!((rat.defense, rat.armor, rat.sight), (DEFAULT_DEFENSE, 0, 8));
+        Raws::parse(DEFAULT_RAWS).expect("The default raws should parse");
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let e = error(&RAT.replace("speed = 100", "speed = 100\nspeeed = 50"));
+        assert!(e.starts_with("10: unknown field `speeed`"), "{e}");
+    }
+
+    #[test]
+    fn rejects_invalid_dice() {
+        let e = error(&RAT.replace(r#"damage = "1d3""#, r#"damage = "1d6--2""#));
+        assert!(e.starts_with("2: rat: invalid dice `1d6--2`"), "{e}");
+        let e = error(&RAT.replace(r#"hp = "1d4""#, r#"hp = "1d4-1""#));
+        assert_eq!(e, "2: rat: hp `1d4-1` can roll 0");
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let e = error(&format!("{RAT}{RAT}"));
+        assert_eq!(e, "11: rat: another monster already has this name");
+    }
+
+    #[test]
+    fn rejects_a_missing_glyph() {
+        let e = error(&RAT.replace("glyph = \"r\"\n", ""));
+        assert!(e.starts_with("2: missing field `glyph`"), "{e}");
+    }
+}
@@ -0,0 +1,21 @@
+use std::{any::{Any, TypeId}, collections::HashMap};
+
+#[derive(Default)]
+pub struct Resources {
+    values: HashMap<TypeId, Box<dyn Any>>
+}
+impl Resources {
+    pub fn insert<R: 'static>(&mut self, resource: R) {
+        self.values.insert(TypeId::of::<R>(), Box::new(resource));
+    }
+
+    pub fn get<R: 'static>(&self) -> Option<&R> {
+        self.values.get(&TypeId::of::<R>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    pub fn get_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        self.values.get_mut(&TypeId::of::<R>())
+            .and_then(|value| value.downcast_mut())
+    }
+}
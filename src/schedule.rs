@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use crate::world::World;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Stage {
+    Input,
+    AI,
+    Movement,
+    Combat,
+    Cleanup,
+    Render
+}
+
+pub type SystemFn = fn(&mut World);
+pub type RunCondition = fn(&World) -> bool;
+
+pub struct SystemConfig {
+    name: &'static str,
+    stage: Stage,
+    run: SystemFn,
+    after: Vec<&'static str>,
+    before: Vec<&'static str>,
+    conditions: Vec<RunCondition>
+}
+impl SystemConfig {
+    pub fn after(&mut self, name: &'static str) -> &mut Self {
+        self.after.push(name);
+        self
+    }
+
+    pub fn before(&mut self, name: &'static str) -> &mut Self {
+        self.before.push(name);
+        self
+    }
+
+    pub fn run_if(&mut self, condition: RunCondition) -> &mut Self {
+        self.conditions.push(condition);
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<SystemConfig>,
+    order: Option<Vec<usize>>
+}
+impl Schedule {
+    pub fn add_system(&mut self, stage: Stage, name: &'static str, run: SystemFn) -> &mut SystemConfig {
+        assert!(
+            self.systems.iter().all(|system| system.name != name),
+            "System {name} is already registered"
+        );
+        self.order = None;
+        self.systems.push(SystemConfig { name, stage, run, after: vec![], before: vec![], conditions: vec![] });
+        self.systems.last_mut().expect("System was just pushed")
+    }
+
+    // Returns (before, after) index pairs for every declared ordering constraint.
+    fn edges(&self) -> Vec<(usize, usize)> {
+        let index: HashMap<&str, usize> = self.systems.iter()
+            .enumerate()
+            .map(|(idx, system)| (system.name, idx))
+            .collect();
+        let lookup = |owner: &str, name: &str| *index.get(name)
+            .unwrap_or_else(|| panic!("System {owner} is ordered against unknown system {name}"));
+        let mut edges = vec![];
+        for (idx, system) in self.systems.iter().enumerate() {
+            for name in &system.after {
+                edges.push((lookup(system.name, name), idx));
+            }
+            for name in &system.before {
+                edges.push((idx, lookup(system.name, name)));
+            }
+        }
+        edges
+    }
+
+    // Sorts by stage, then topologically by constraints, falling back to registration order.
+    fn build_order(&self) -> Vec<usize> {
+        let edges = self.edges();
+        for (first, second) in &edges {
+            let (first, second) = (&self.systems[*first], &self.systems[*second]);
+            assert!(
+                first.stage <= second.stage,
+                "System {} ({:?}) cannot run before {} ({:?})", first.name, first.stage, second.name, second.stage
+            );
+        }
+        let mut pending: Vec<usize> = (0..self.systems.len()).collect();
+        pending.sort_by_key(|idx| self.systems[*idx].stage);
+        let mut order = Vec::with_capacity(pending.len());
+        while !pending.is_empty() {
+            let stage = self.systems[pending[0]].stage;
+            let ready = pending.iter()
+                .position(|idx| {
+                    self.systems[*idx].stage == stage
+                        && edges.iter().all(|(first, second)| second != idx || order.contains(first))
+                })
+                .unwrap_or_else(|| panic!("Systems in stage {stage:?} have cyclic ordering constraints"));
+            order.push(pending.remove(ready));
+        }
+        order
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        if self.order.is_none() {
+            self.order = Some(self.build_order());
+        }
+        let order = self.order.as_ref().expect("Order was just built");
+        for idx in order {
+            let system = &self.systems[*idx];
+            if system.conditions.iter().all(|condition| condition(world)) {
+                (system.run)(world);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::map::Map;
+
+    use super::*;
+
+    // The names of the systems that ran, in order.
+    #[derive(Default)]
+    struct Trace(Vec<&'static str>);
+
+    fn a(world: &mut World) {
+        world.resource_mut::<Trace>().0.push("a");
+    }
+
+    fn b(world: &mut World) {
+        world.resource_mut::<Trace>().0.push("b");
+    }
+
+    fn c(world: &mut World) {
+        world.resource_mut::<Trace>().0.push("c");
+    }
+
+    fn d(world: &mut World) {
+        world.resource_mut::<Trace>().0.push("d");
+    }
+
+    fn run(schedule: &mut Schedule) -> Vec<&'static str> {
+        let mut world = World::new(Map::new(1, 1));
+        world.insert_resource(Trace::default());
+        schedule.run(&mut world);
+        std::mem::take(&mut world.resource_mut::<Trace>().0)
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut schedule = Schedule::default();
+        schedule.add_system(Stage::Render, "a", a);
+        schedule.add_system(Stage::Combat, "b", b);
+        schedule.add_system(Stage::Input, "c", c);
+        schedule.add_system(Stage::Combat, "d", d);
+        assert_eq!(run(&mut schedule), ["c", "b", "d", "a"]);
+    }
+
+    #[test]
+    fn constraints_order_systems_within_a_stage() {
+        let mut schedule = Schedule::default();
+        schedule.add_system(Stage::Cleanup, "a", a)
+            .after("c");
+        schedule.add_system(Stage::Cleanup, "b", b)
+            .before("c");
+        schedule.add_system(Stage::Cleanup, "c", c);
+        schedule.add_system(Stage::Cleanup, "d", d)
+            .after("a");
+        assert_eq!(run(&mut schedule), ["b", "c", "a", "d"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cyclic ordering constraints")]
+    fn cycles_panic() {
+        let mut schedule = Schedule::default();
+        schedule.add_system(Stage::Cleanup, "a", a)
+            .after("b");
+        schedule.add_system(Stage::Cleanup, "b", b)
+            .after("c");
+        schedule.add_system(Stage::Cleanup, "c", c)
+            .after("a");
+        run(&mut schedule);
+    }
+
+    #[test]
+    #[should_panic(expected = "System a (Render) cannot run before b (Input)")]
+    fn constraints_cannot_cross_stages_backwards() {
+        let mut schedule = Schedule::default();
+        schedule.add_system(Stage::Render, "a", a)
+            .before("b");
+        schedule.add_system(Stage::Input, "b", b);
+        run(&mut schedule);
+    }
+
+    #[test]
+    #[should_panic(expected = "System a is ordered against unknown system z")]
+    fn unknown_systems_panic() {
+        let mut schedule = Schedule::default();
+        schedule.add_system(Stage::Input, "a", a)
+            .after("z");
+        run(&mut schedule);
+    }
+
+    #[test]
+    fn run_if_skips_systems_whose_condition_fails() {
+        let mut schedule = Schedule::default();
+        schedule.add_system(Stage::Input, "a", a)
+            .run_if(|_| false);
+        schedule.add_system(Stage::Input, "b", b)
+            .run_if(|_| true);
+        schedule.add_system(Stage::Input, "c", c)
+            .run_if(|_| true)
+            .run_if(|world| world.resource::<Trace>().0.is_empty());
+        assert_eq!(run(&mut schedule), ["b"]);
+    }
+}
@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+
+use crate::{components::Position, world::Entity};
+
+// Which entities stand on each tile of the loaded level. `World` keeps it in
+// step with every `Position`, including as levels are detached and attached.
+#[derive(Default)]
+pub struct SpatialIndex {
+    tiles: HashMap<(usize, usize), Vec<Entity>>
+}
+impl SpatialIndex {
+    pub fn insert(&mut self, entity: Entity, pos: &Position) {
+        self.tiles.entry((pos.x, pos.y)).or_default().push(entity);
+    }
+
+    pub fn remove(&mut self, entity: Entity, pos: &Position) {
+        let Some(entities) = self.tiles.get_mut(&(pos.x, pos.y)) else {
+            return;
+        };
+        entities.retain(|other| *other != entity);
+        if entities.is_empty() {
+            self.tiles.remove(&(pos.x, pos.y));
+        }
+    }
+
+    pub fn at(&self, x: usize, y: usize) -> &[Entity] {
+        self.tiles.get(&(x, y)).map_or(&[], Vec::as_slice)
+    }
+}
@@ -0,0 +1,67 @@
+use std::ops::RangeInclusive;
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::{components::MonsterKind, raws::Raws};
+
+#[derive(Clone)]
+struct SpawnEntry<T> {
+    value: T,
+    weight: usize,
+    depth: RangeInclusive<usize>
+}
+
+// A weighted table where every entry is only available on some depths.
+#[derive(Clone)]
+pub struct SpawnTable<T> {
+    entries: Vec<SpawnEntry<T>>
+}
+impl<T: Clone> SpawnTable<T> {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    pub fn with(mut self, value: T, weight: usize, depth: RangeInclusive<usize>) -> Self {
+        self.entries.push(SpawnEntry { value, weight, depth });
+        self
+    }
+
+    // Picks an entry allowed at `depth` by weight, or `None` if there are none.
+    pub fn roll(&self, depth: usize, rng: &mut StdRng) -> Option<T> {
+        let total: usize = self.entries.iter()
+            .filter(|entry| entry.depth.contains(&depth))
+            .map(|entry| entry.weight)
+            .sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.random_range(0..total);
+        for entry in self.entries.iter().filter(|entry| entry.depth.contains(&depth)) {
+            if roll < entry.weight {
+                return Some(entry.value.clone());
+            }
+            roll -= entry.weight;
+        }
+        None
+    }
+}
+
+// What a freshly generated level gets filled with.
+#[derive(Clone)]
+pub struct SpawnTables {
+    pub monsters: SpawnTable<MonsterKind>,
+    // How many monsters a single room or cave region holds.
+    pub per_room: SpawnTable<usize>
+}
+impl SpawnTables {
+    pub fn new(raws: &Raws) -> Self {
+        Self {
+            monsters: raws.monster_table(),
+            per_room: SpawnTable::new()
+                .with(0, 4, 0..=usize::MAX)
+                .with(1, 4, 0..=usize::MAX)
+                .with(2, 2, 1..=usize::MAX)
+                .with(3, 1, 3..=usize::MAX)
+        }
+    }
+}
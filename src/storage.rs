@@ -0,0 +1,245 @@
+use std::{any::{type_name, Any, TypeId}, collections::HashMap};
+
+use crate::world::Entity;
+
+pub trait Column: Any {
+    fn new_empty(&self) -> Box<dyn Column>;
+    fn swap_remove_into(&mut self, row: usize, other: &mut dyn Column);
+    fn swap_remove_drop(&mut self, row: usize);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+impl<T: 'static> Column for Vec<T> {
+    fn new_empty(&self) -> Box<dyn Column> {
+        Box::new(Vec::<T>::new())
+    }
+
+    fn swap_remove_into(&mut self, row: usize, other: &mut dyn Column) {
+        let value = self.swap_remove(row);
+        other.as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Columns should hold the same component type")
+            .push(value);
+    }
+
+    fn swap_remove_drop(&mut self, row: usize) {
+        self.swap_remove(row);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Clone, Default)]
+pub struct ArchetypeKey(Vec<TypeId>);
+impl ArchetypeKey {
+    pub fn new(mut types: Vec<TypeId>) -> Self {
+        types.sort();
+        types.dedup();
+        Self(types)
+    }
+
+    pub fn types(&self) -> &[TypeId] {
+        &self.0
+    }
+
+    pub fn contains(&self, id: TypeId) -> bool {
+        self.0.binary_search(&id).is_ok()
+    }
+
+    pub fn with(&self, id: TypeId) -> Self {
+        let mut types = self.0.clone();
+        if let Err(idx) = types.binary_search(&id) {
+            types.insert(idx, id);
+        }
+        Self(types)
+    }
+
+    pub fn without(&self, id: TypeId) -> Self {
+        let mut types = self.0.clone();
+        if let Ok(idx) = types.binary_search(&id) {
+            types.remove(idx);
+        }
+        Self(types)
+    }
+}
+
+pub struct Table {
+    key: ArchetypeKey,
+    entities: Vec<Entity>,
+    columns: HashMap<TypeId, Box<dyn Column>>
+}
+impl Table {
+    pub fn new(key: ArchetypeKey, mut columns: HashMap<TypeId, Box<dyn Column>>) -> Self {
+        columns.retain(|id, _| key.contains(*id));
+        Self { key, entities: vec![], columns }
+    }
+
+    pub fn key(&self) -> &ArchetypeKey {
+        &self.key
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn has<T: 'static>(&self) -> bool {
+        self.key.contains(TypeId::of::<T>())
+    }
+
+    pub fn column<T: 'static>(&self) -> Option<&[T]> {
+        self.columns.get(&TypeId::of::<T>())
+            .and_then(|c| c.as_any().downcast_ref::<Vec<T>>())
+            .map(|c| c.as_slice())
+    }
+
+    pub fn column_mut<T: 'static>(&mut self) -> Option<&mut [T]> {
+        self.vec_mut::<T>().map(|c| c.as_mut_slice())
+    }
+
+    pub fn vec_mut<T: 'static>(&mut self) -> Option<&mut Vec<T>> {
+        self.columns.get_mut(&TypeId::of::<T>())
+            .and_then(|c| c.as_any_mut().downcast_mut::<Vec<T>>())
+    }
+
+    pub fn push<T: 'static>(&mut self, value: T) {
+        self.vec_mut::<T>()
+            .unwrap_or_else(|| panic!("Table should have a {} column", type_name::<T>()))
+            .push(value);
+    }
+
+    pub fn push_entity(&mut self, entity: Entity) {
+        self.entities.push(entity);
+    }
+
+    pub fn empty_columns(&self) -> HashMap<TypeId, Box<dyn Column>> {
+        self.columns.iter()
+            .map(|(id, column)| (*id, column.new_empty()))
+            .collect()
+    }
+
+    // Moves every column shared with `other` into it and drops the rest.
+    // Returns the entity that was swapped into `row`, if any.
+    pub fn move_row(&mut self, row: usize, other: &mut Table) -> Option<Entity> {
+        self.move_row_except(row, other, None)
+    }
+
+    // Like `move_row`, but hands the `T` value back instead of dropping it.
+    pub fn take_row<T: 'static>(&mut self, row: usize, other: &mut Table) -> (T, Option<Entity>) {
+        let value = self.vec_mut::<T>()
+            .unwrap_or_else(|| panic!("Table should have a {} column", type_name::<T>()))
+            .swap_remove(row);
+        (value, self.move_row_except(row, other, Some(TypeId::of::<T>())))
+    }
+
+    fn move_row_except(&mut self, row: usize, other: &mut Table, skip: Option<TypeId>) -> Option<Entity> {
+        for (id, column) in &mut self.columns {
+            if Some(*id) == skip {
+                continue;
+            }
+            match other.columns.get_mut(id) {
+                Some(target) => column.swap_remove_into(row, target.as_mut()),
+                None => column.swap_remove_drop(row)
+            }
+        }
+        other.entities.push(self.entities.swap_remove(row));
+        self.entities.get(row).copied()
+    }
+
+    // Moves the row out of the table entirely so it can be attached to another world later.
+    // Returns the entity that was swapped into `row`, if any.
+    pub fn detach_row(&mut self, row: usize) -> (DetachedRow, Option<Entity>) {
+        let mut columns = self.empty_columns();
+        for (id, column) in &mut self.columns {
+            let target = columns.get_mut(id).expect("Empty columns mirror the table");
+            column.swap_remove_into(row, target.as_mut());
+        }
+        self.entities.swap_remove(row);
+        (DetachedRow { key: self.key.clone(), columns }, self.entities.get(row).copied())
+    }
+
+    pub fn attach_row(&mut self, entity: Entity, row: DetachedRow) {
+        for (id, mut column) in row.columns {
+            let target = self.columns.get_mut(&id).expect("Table should match the detached row");
+            column.swap_remove_into(0, target.as_mut());
+        }
+        self.entities.push(entity);
+    }
+
+    // Drops the row entirely. Returns the entity that was swapped into `row`, if any.
+    pub fn remove_row(&mut self, row: usize) -> Option<Entity> {
+        for column in self.columns.values_mut() {
+            column.swap_remove_drop(row);
+        }
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+}
+
+// A single entity's components, held outside of any world.
+pub struct DetachedRow {
+    key: ArchetypeKey,
+    columns: HashMap<TypeId, Box<dyn Column>>
+}
+impl DetachedRow {
+    pub fn key(&self) -> &ArchetypeKey {
+        &self.key
+    }
+
+    pub fn empty_columns(&self) -> HashMap<TypeId, Box<dyn Column>> {
+        self.columns.iter()
+            .map(|(id, column)| (*id, column.new_empty()))
+            .collect()
+    }
+}
+
+pub trait Bundle: 'static {
+    fn type_ids() -> Vec<TypeId>;
+    fn empty_columns() -> HashMap<TypeId, Box<dyn Column>>;
+    fn push_into(self, table: &mut Table);
+}
+
+macro_rules! impl_bundle {
+    ($($name:ident),+) => {
+        impl<$($name: 'static),+> Bundle for ($($name,)+) {
+            fn type_ids() -> Vec<TypeId> {
+                vec![$(TypeId::of::<$name>()),+]
+            }
+
+            fn empty_columns() -> HashMap<TypeId, Box<dyn Column>> {
+                let mut columns: HashMap<TypeId, Box<dyn Column>> = HashMap::new();
+                $(columns.insert(TypeId::of::<$name>(), Box::new(Vec::<$name>::new()));)+
+                columns
+            }
+
+            #[allow(non_snake_case)]
+            fn push_into(self, table: &mut Table) {
+                let ($($name,)+) = self;
+                $(table.push($name);)+
+            }
+        }
+    };
+}
+
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+impl_bundle!(A, B, C, D, E, F, G, H, I);
+impl_bundle!(A, B, C, D, E, F, G, H, I, J);
+impl_bundle!(A, B, C, D, E, F, G, H, I, J, K);
+impl_bundle!(A, B, C, D, E, F, G, H, I, J, K, L);
+impl_bundle!(A, B, C, D, E, F, G, H, I, J, K, L, M);
@@ -1,227 +1,615 @@
 use std::{io::{stdout, Write}, time::Duration};
 
-use crossterm::{cursor, event::{poll, read, Event, KeyCode}, style, terminal, QueueableCommand};
-use rand::Rng;
+use crossterm::{cursor, event::{poll, read, Event, KeyCode}, style::{self, Color, Stylize}, terminal::{self, ClearType}, QueueableCommand};
+use rand::{rngs::StdRng, Rng};
 
-use crate::{components::{AggressionIntent, Damage, Position}, game::TurnState, world::{ArchetypeKey, Entity, World}};
+use crate::{combat::{self, AttackOutcome, AttackerStats, DefenderStats, HitKind, DEFAULT_DEFENSE}, components::{AIState, Armor, Behaviour, BlocksTile, Damage, Defense, Enemy, Energy, Gold, MaxHP, MonsterKind, MovementIntent, Player, Position, Speed, Strength, Treasure, AI, HP}, dungeon::{Dungeon, StairsDirection}, events::{Action, ActionTaken, AttackEvent, CloseDoors, DamageDealt, EntityDied, GoldPickedUp, Moved, UseStairs}, fov::compute_fov, game::{GameConfig, InView, MessageLog, TurnCounter, TurnState}, pathfinding::{a_star_by, manhattan_distance, neighbours}, query::{With, Without}, raws::Raws, schedule::{Schedule, Stage}, tile::Tile, world::{Entity, World}};
+
+pub fn is_enemy_turn(world: &World) -> bool {
+    *world.resource::<TurnState>() == TurnState::Enemy
+}
+
+pub fn register_systems(schedule: &mut Schedule) {
+    schedule.add_system(Stage::Input, "input", InputSystem::run);
+    schedule.add_system(Stage::AI, "ai", AISystem::run)
+        .run_if(is_enemy_turn);
+    schedule.add_system(Stage::Movement, "movement", MovementSystem::run);
+    schedule.add_system(Stage::Movement, "pickup", PickupSystem::run)
+        .after("movement");
+    schedule.add_system(Stage::Movement, "doors", DoorSystem::run)
+        .after("movement");
+    schedule.add_system(Stage::Combat, "damage", DamageSystem::run);
+    schedule.add_system(Stage::Cleanup, "death", DeathSystem::run)
+        .after("damage");
+    schedule.add_system(Stage::Cleanup, "despawn", DespawnSystem::run)
+        .after("death");
+    schedule.add_system(Stage::Cleanup, "stairs", StairsSystem::run)
+        .after("despawn")
+        .before("energy")
+        .before("fov");
+    schedule.add_system(Stage::Cleanup, "energy", EnergySystem::run)
+        .after("despawn");
+    schedule.add_system(Stage::Cleanup, "time", TimeSystem::run)
+        .after("energy");
+    schedule.add_system(Stage::Cleanup, "fov", FovSystem::run)
+        .after("despawn");
+    schedule.add_system(Stage::Cleanup, "log", LogSystem::run)
+        .after("death")
+        .before("despawn");
+    schedule.add_system(Stage::Render, "render", RenderSystem::run);
+}
 
 pub struct InputSystem;
 impl InputSystem {
-    pub fn run(world: &mut World) -> bool {
-        if let Ok(true) = poll(Duration::from_secs(0)) {
-            match read() {
-                Ok(Event::Key(event)) => {
-                    match event.code {
-                        KeyCode::Esc => std::process::exit(0),
-                        KeyCode::Char(c) => {
-                            let possibilities = ['w', 'W', 'a', 'A', 's', 'S', 'd', 'D'];
-                            if !possibilities.contains(&c) {
-                                return false;
-                            }
-                            for (key, table) in &mut world.tables {
-                                if !key.has_position || !key.is_controllable {
-                                    continue;
-                                }
-                                for pos in &mut table.positions {
-                                    let (x, y) = match c {
-                                        'w' | 'W' => (pos.x, pos.y - 1),
-                                        'a' | 'A' => (pos.x - 1, pos.y),
-                                        's' | 'S' => (pos.x, pos.y + 1),
-                                        'd' | 'D' => (pos.x + 1, pos.y),
-                                        _ => return false
-                                    };
-                                    if world.map.is_walkable(x, y) {
-                                        *pos = Position::new(x, y);
-                                        return true
-                                    }
-
-                                }
-                            }
-                        },
-                        _ => return false
-                    }
-                },
-                Err(e) =>{
-                    eprintln!("error: {e}");
-                    std::process::exit(1);
-                },
-                _ => return false
+    fn read_key() -> Option<KeyCode> {
+        if !matches!(poll(Duration::from_secs(0)), Ok(true)) {
+            return None;
+        }
+        match read() {
+            Ok(Event::Key(event)) => Some(event.code),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            },
+            _ => None
+        }
+    }
+
+    // Runs every frame so quitting works whatever the turn. Anything else pressed
+    // outside the player's turn is dropped.
+    pub fn run(world: &mut World) {
+        let Some(key) = Self::read_key() else {
+            return;
+        };
+        if key == KeyCode::Esc {
+            std::process::exit(0);
+        }
+        if *world.resource::<TurnState>() != TurnState::Player {
+            return;
+        }
+        match key {
+            KeyCode::Char(' ' | '.') => {
+                let players: Vec<Entity> = world.query_ref::<(), With<Player>>()
+                    .map(|(player, _)| player)
+                    .collect();
+                for player in players {
+                    world.send_event(ActionTaken { entity: player, action: Action::Rest });
+                }
+            },
+            KeyCode::Char(c @ ('<' | '>')) => {
+                let direction = if c == '<' { StairsDirection::Up } else { StairsDirection::Down };
+                let players: Vec<Entity> = world.query_ref::<(), With<Player>>()
+                    .map(|(player, _)| player)
+                    .collect();
+                for player in players {
+                    world.send_event(UseStairs { entity: player, direction });
+                }
+            },
+            KeyCode::Char('c' | 'C') => {
+                let players: Vec<Entity> = world.query_ref::<(), With<Player>>()
+                    .map(|(player, _)| player)
+                    .collect();
+                for player in players {
+                    world.send_event(CloseDoors { entity: player });
+                }
+            },
+            KeyCode::Char(c) => {
+                let possibilities = ['w', 'W', 'a', 'A', 's', 'S', 'd', 'D'];
+                if !possibilities.contains(&c) {
+                    return;
+                }
+                let players: Vec<(Entity, Position)> = world.query_ref::<&Position, With<Player>>()
+                    .map(|(player, pos)| (player, pos.clone()))
+                    .collect();
+                for (player, pos) in players {
+                    let (x, y) = match c {
+                        'w' | 'W' => (pos.x, pos.y - 1),
+                        'a' | 'A' => (pos.x - 1, pos.y),
+                        's' | 'S' => (pos.x, pos.y + 1),
+                        'd' | 'D' => (pos.x + 1, pos.y),
+                        _ => return
+                    };
+                    world.insert(player, MovementIntent(Position::new(x, y)));
+                }
+            },
+            _ => ()
+        }
+    }
+}
+
+pub struct MovementSystem;
+impl MovementSystem {
+    pub fn run(world: &mut World) {
+        let moves: Vec<(Entity, Position)> = world.query_ref::<&MovementIntent, With<Position>>()
+            .map(|(entity, intent)| (entity, intent.0.clone()))
+            .collect();
+        for (entity, target) in moves {
+            world.remove::<MovementIntent>(entity);
+            // Walking into someone attacks them if they're hostile. Anyone else
+            // just gets in the way, which costs a monster its turn.
+            if world.has::<BlocksTile>(entity)
+                && let Some(other) = world.blocking_at(target.x, target.y)
+                && other != entity {
+                if combat::are_hostile(world, entity, other) {
+                    world.send_event(AttackEvent { attacker: entity, defender: other });
+                    world.send_event(ActionTaken { entity, action: Action::Attack });
+                } else if !world.has::<Player>(entity) {
+                    world.send_event(ActionTaken { entity, action: Action::Rest });
+                }
+                continue;
+            }
+            // Bumping into a closed door opens it instead of moving.
+            if world.map.tile_at(target.x, target.y) == Some(Tile::DoorClosed) {
+                world.map.set_tile(target.x, target.y, Tile::DoorOpen);
+                world.send_event(ActionTaken { entity, action: Action::UseDoor });
+                if world.has::<Player>(entity) {
+                    world.resource_mut::<MessageLog>().push("You open the door".to_string());
+                }
+                continue;
+            }
+            if world.map.is_walkable(target.x, target.y) && world.has::<Position>(entity) {
+                world.insert(entity, target.clone());
+                world.send_event(Moved { entity, to: target });
+                world.send_event(ActionTaken { entity, action: Action::Move });
+            }
+        }
+    }
+}
+
+// Anyone carrying a purse picks up the treasure they walk onto.
+pub struct PickupSystem;
+impl PickupSystem {
+    pub fn run(world: &mut World) {
+        let moves: Vec<(Entity, Position)> = world.events::<Moved>()
+            .iter_current()
+            .filter(|moved| world.has::<Gold>(moved.entity))
+            .map(|moved| (moved.entity, moved.to.clone()))
+            .collect();
+        for (entity, to) in moves {
+            let found: Vec<(Entity, usize)> = world.entities_at(to.x, to.y)
+                .iter()
+                .filter_map(|treasure| Some((*treasure, world.get::<Treasure>(*treasure)?.0)))
+                .collect();
+            for (treasure, value) in found {
+                world.despawn(treasure);
+                let Some(gold) = world.get_mut::<Gold>(entity) else {
+                    continue;
+                };
+                gold.0 += value;
+                let total = gold.0;
+                world.send_event(GoldPickedUp { entity, value, total });
             }
         }
-        false
+    }
+}
+
+// Closes every open door next to whoever asked, unless something stands in it.
+pub struct DoorSystem;
+impl DoorSystem {
+    pub fn run(world: &mut World) {
+        let requests: Vec<Entity> = world.events::<CloseDoors>()
+            .iter_current()
+            .map(|request| request.entity)
+            .collect();
+        for entity in requests {
+            let Some(pos) = world.get::<Position>(entity).cloned() else {
+                continue;
+            };
+            let doors: Vec<Position> = [(0, -1), (-1, 0), (0, 1), (1, 0)]
+                .into_iter()
+                .filter_map(|(dx, dy)| Some(Position::new(pos.x.checked_add_signed(dx)?, pos.y.checked_add_signed(dy)?)))
+                .filter(|door| world.map.tile_at(door.x, door.y) == Some(Tile::DoorOpen))
+                .collect();
+            let (blocked, free): (Vec<Position>, Vec<Position>) = doors.into_iter()
+                .partition(|door| !world.entities_at(door.x, door.y).is_empty());
+            let message = match (free.is_empty(), blocked.is_empty()) {
+                (true, true) => "There is no open door here",
+                (true, false) => "Something is in the way",
+                (false, _) => "You close the door"
+            };
+            for door in &free {
+                world.map.set_tile(door.x, door.y, Tile::DoorClosed);
+            }
+            if !free.is_empty() {
+                world.send_event(ActionTaken { entity, action: Action::UseDoor });
+            }
+            world.resource_mut::<MessageLog>().push(message.to_string());
+        }
     }
 }
 
 pub struct RenderSystem;
 impl RenderSystem {
+    const LOG_LINES: usize = 3;
+
     fn render_xy(x: usize, y: usize) -> (usize, usize) {
         (x, y + 1)
     }
 
+    pub fn run(world: &mut World) {
+        if let Err(e) = Self::render(world) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+    }
+
     pub fn render(world: &World) -> std::io::Result<()> {
         let mut stdout = stdout();
-        for (key, table) in &world.tables {
-            if !key.has_hp {
-                continue;
-            }
-            if key.is_controllable {
-                if let Some(hp) = table.hitpoints.first() {
-                    stdout
-                        .queue(cursor::MoveTo(0, 0))?
-                        .queue(style::Print(format!("hp: {}", hp.0)))?;
-                }
-            } else if key.is_enemy {
-                if let Some(hp) = table.hitpoints.first() {
-                    stdout  
-                        .queue(cursor::MoveTo(10, 0))?
-                        .queue(style::Print(format!("enemy hp: {}", hp.0)))?;
-                }
-            }
+        // The status line's fields follow one another, so none can run into the next.
+        let mut status: Vec<String> = vec![];
+        if let Some((_, (hp, max_hp))) = world.query_ref::<(&HP, &MaxHP), With<Player>>().next() {
+            status.push(format!("hp: {}/{}", hp.0, max_hp.0));
         }
+        status.push(format!("depth: {}", world.resource::<Dungeon>().depth()));
+        let in_view = &world.resource::<InView>().0;
+        // Shows whoever the player last hurt or was hurt by, or else the first enemy in view.
+        let foe = world.events::<DamageDealt>()
+            .iter()
+            .filter_map(|damage| match (world.has::<Player>(damage.attacker), world.has::<Player>(damage.defender)) {
+                (true, _) => Some(damage.defender),
+                (_, true) => Some(damage.attacker),
+                _ => None
+            })
+            .filter(|entity| world.is_alive(*entity))
+            .last()
+            .or_else(|| in_view.iter().copied().find(|entity| world.has::<Enemy>(*entity)));
+        if let Some((kind, hp, max_hp)) = foe.and_then(|foe| Some((world.get::<MonsterKind>(foe)?, world.get::<HP>(foe)?, world.get::<MaxHP>(foe)?))) {
+            status.push(format!("{} {}/{}", world.resource::<Raws>().monster(*kind).name, hp.0, max_hp.0));
+        }
+        status.push(format!("turn: {}", world.resource::<TurnCounter>().0));
+        status.push(match world.resource::<TurnState>() {
+            TurnState::Player => "your turn",
+            TurnState::Enemy => "enemy turn",
+            TurnState::GameOver => "game over"
+        }.to_string());
+        stdout
+            .queue(cursor::MoveTo(0, 0))?
+            .queue(style::Print(status.join("  ")))?
+            .queue(terminal::Clear(ClearType::UntilNewLine))?;
         for x in 0..world.map.columns() {
             for y in 0..world.map.rows() {
-                let idx = world.map.xy_idx(usize::from(x), usize::from(y));
-                if let Some(ch) = world.map.get_tile(idx) {
+                let idx = world.map.xy_idx(x, y);
+                if let Some(tile) = world.map.get_tile(idx) {
+                    let tile = if world.map.is_visible(x, y) {
+                        tile.glyph().with(tile.color())
+                    } else if world.map.is_revealed(x, y) {
+                        tile.glyph().with(Color::DarkGrey)
+                    } else {
+                        ' '.with(Color::Reset)
+                    };
                     let (x, y) = Self::render_xy(x, y);
                     stdout
                         .queue(cursor::MoveTo(x as u16, y as u16))?
-                        .queue(style::Print(ch))?;                    
+                        .queue(style::PrintStyledContent(tile))?;                    
                 }
             }            
         }
-        for (key, table) in &world.tables {
-            if !key.has_position {
+        let damaged: Vec<Entity> = world.events::<DamageDealt>()
+            .iter()
+            .filter(|damage| damage.amount > 0)
+            .map(|damage| damage.defender)
+            .collect();
+        // Items go down first so anyone standing on them is drawn on top.
+        let mut glyphs: Vec<(bool, &Position, char, Color)> = vec![];
+        for &entity in in_view {
+            let Some(pos) = world.get::<Position>(entity) else {
                 continue;
+            };
+            let color = if damaged.contains(&entity) { Color::Red } else { Color::Reset };
+            if world.has::<Player>(entity) {
+                glyphs.push((true, pos, '@', color));
+            } else if let Some(kind) = world.get::<MonsterKind>(entity) {
+                let monster = world.resource::<Raws>().monster(*kind);
+                let color = if damaged.contains(&entity) { Color::Red } else { monster.colour };
+                glyphs.push((true, pos, monster.glyph, color));
+            } else if world.has::<Treasure>(entity) {
+                glyphs.push((false, pos, '$', Color::Yellow));
             }
-            for pos in &table.positions {
-                if key.is_controllable {
-                    let (x, y) = Self::render_xy(pos.x, pos.y);
-                    stdout
-                        .queue(cursor::MoveTo(x as u16, y as u16))?
-                        .queue(style::Print('@'))?;
-                } else if key.is_enemy {
-                    let (x, y) = Self::render_xy(pos.x, pos.y);
-                    stdout
-                        .queue(cursor::MoveTo(x as u16, y as u16))?
-                        .queue(style::Print('g'))?;
-                }
-            }
-            for aggro_intent in &table.aggression_intents {
-                if let Some(aggro) = aggro_intent {
-                    stdout  
-                        .queue(cursor::MoveTo(0, 10))?
-                        .queue(style::Print(format!("attacking {}!", aggro.0)))?;
-                }
-            }
-        }        
+        }
+        glyphs.sort_by_key(|(actor, ..)| *actor);
+        for (_, pos, glyph, color) in glyphs {
+            let (x, y) = Self::render_xy(pos.x, pos.y);
+            stdout
+                .queue(cursor::MoveTo(x as u16, y as u16))?
+                .queue(style::PrintStyledContent(glyph.with(color)))?;
+        }
+        let log_y = world.map.rows() + 1;
+        for (idx, message) in world.resource::<MessageLog>().recent(Self::LOG_LINES).iter().enumerate() {
+            stdout
+                .queue(cursor::MoveTo(0, (log_y + idx) as u16))?
+                .queue(style::Print(message))?
+                .queue(terminal::Clear(ClearType::UntilNewLine))?;
+        }
+        // A u64 seed runs to 20 digits, so it gets a line of its own.
+        stdout
+            .queue(cursor::MoveTo(0, (log_y + Self::LOG_LINES) as u16))?
+            .queue(style::Print(format!("seed: {}", world.resource::<GameConfig>().seed)))?
+            .queue(terminal::Clear(ClearType::UntilNewLine))?;
         stdout.flush()        
     }
 }
 
-pub struct AggressionSystem;
-impl AggressionSystem {
+pub struct DamageSystem;
+impl DamageSystem {
     pub fn run(world: &mut World) {
-        let player_key = ArchetypeKey {
-            has_position: true,
-            is_controllable: true,
-            is_enemy: false,
-            has_hp: true,
-            has_strength: true,
-        };
-        let player_position = match world.tables.get(&player_key) {
-            Some(table) => match table.positions.first() {
-                Some(pos) => pos.clone(),
-                None => return,
-            },
-            None => return,
-        };       
-        let mut to_aggro: Vec<(Entity, Entity)> = vec![];
-        for (key, enemy_table) in &mut world.tables {
-            if !key.has_position || !key.has_hp || !key.has_strength || !key.is_enemy {
-                continue;                
+        let attacks: Vec<(Entity, AttackerStats, Entity, DefenderStats)> = world.events::<AttackEvent>()
+            .iter_current()
+            .filter(|attack| world.is_alive(attack.defender))
+            .filter_map(|attack| {
+                let stats = AttackerStats {
+                    strength: world.get::<Strength>(attack.attacker)?.0,
+                    damage: world.get::<Damage>(attack.attacker)?.0
+                };
+                let against = DefenderStats {
+                    defense: world.get::<Defense>(attack.defender).map_or(DEFAULT_DEFENSE, |defense| defense.0),
+                    armor: world.get::<Armor>(attack.defender).map_or(0, |armor| armor.0)
+                };
+                Some((attack.attacker, stats, attack.defender, against))
+            })
+            .collect();
+        for (attacker, stats, defender, against) in attacks {
+            // Someone else may already have finished it off this turn.
+            if world.get::<HP>(defender).is_none_or(|hp| hp.0 == 0) {
+                continue;
+            }
+            let outcome = combat::resolve(attacker, &stats, defender, &against, world.resource_mut::<StdRng>());
+            if let Some(hp) = world.get_mut::<HP>(defender) {
+                hp.0 = hp.0.saturating_sub(outcome.damage);
             }
-            for (idx, enemy_position) in enemy_table.positions.iter().enumerate() {
-                if &player_position == enemy_position {
-                    enemy_table.aggression_intents[idx] = Some(AggressionIntent(0)); // Enemy attacks player 
-                    let enemy = enemy_table.entities[idx];
-                    to_aggro.push((enemy, 0)); // (Attacker, Defender)
-                }                
+            if outcome.damage > 0 {
+                world.send_event(DamageDealt { attacker, defender, amount: outcome.damage });
             }
+            world.send_event(outcome);
         }
-        if let Some(player_table) = world.tables.get_mut(&player_key) {            
-            for (enemy, player) in to_aggro {
-                let player_idx = player_table.entities
-                    .iter()                    
-                    .position(|e| *e == player)
-                    .expect("Player should already be spawned");
-                player_table.aggression_intents[player_idx] = Some(AggressionIntent(enemy));
-            } 
+    }
+}
+
+pub struct DeathSystem;
+impl DeathSystem {
+    pub fn run(world: &mut World) {
+        let to_remove: Vec<Entity> = world.query_ref::<&HP, ()>()
+            .filter(|(_, hp)| hp.0 == 0)
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in to_remove {
+            world.send_event(EntityDied { entity });
         }
     }
 }
 
-pub struct DamageSystem;
-impl DamageSystem {
+// Runs after everything that reacts to `EntityDied`, so those systems can still
+// look at the dead entity's components.
+pub struct DespawnSystem;
+impl DespawnSystem {
     pub fn run(world: &mut World) {
-        let mut rng = rand::rng();
-        let mut to_damage: Vec<(Entity, Damage)> = vec![];
-        for (key, table) in &mut world.tables {
-            if !key.has_strength {
+        let dead: Vec<Entity> = world.events::<EntityDied>()
+            .iter_current()
+            .map(|death| death.entity)
+            .collect();
+        for entity in dead {
+            if world.has::<Player>(entity) {
+                *world.resource_mut::<TurnState>() = TurnState::GameOver;
+                world.resource_mut::<MessageLog>().push("Press Esc to quit".to_string());
+            }
+            world.despawn(entity);
+        }
+    }
+}
+
+pub struct AISystem;
+impl AISystem {
+    fn next_state(ai: &AI, hp: usize, max_hp: usize, distance: usize, in_sight: bool, rng: &mut StdRng) -> AIState {
+        let sees_player = distance <= ai.sight_radius && in_sight;
+        let flees = match ai.behaviour {
+            Behaviour::Hunter => hp * 3 <= max_hp,
+            Behaviour::Berserker => false,
+            Behaviour::Coward => true
+        };
+        match ai.state {
+            _ if sees_player && flees => AIState::Fleeing,
+            _ if sees_player => AIState::Hunting,
+            AIState::Hunting | AIState::Fleeing => AIState::Wandering,
+            AIState::Idle if rng.random_bool(0.1) => AIState::Wandering,
+            AIState::Wandering if rng.random_bool(0.1) => AIState::Idle,
+            state => state
+        }
+    }
+
+    pub fn run(world: &mut World) {
+        let Some((player, player_position)) = world.query_ref::<&Position, With<Player>>()
+            .next()
+            .map(|(player, pos)| (player, pos.clone())) else {
+            return;
+        };
+        let enemies: Vec<(Entity, Position)> = world.query_ref::<(&Position, &Energy), (With<Enemy>, With<AI>)>()
+            .filter(|(_, (_, energy))| energy.is_ready())
+            .map(|(enemy, (pos, _))| (enemy, pos.clone()))
+            .collect();
+        for (enemy, pos) in enemies {
+            let distance = manhattan_distance(&pos, &player_position);
+            let hp = world.get::<HP>(enemy).map_or(0, |hp| hp.0);
+            let max_hp = world.get::<MaxHP>(enemy).map_or(hp, |max_hp| max_hp.0);
+            let Some(ai) = world.get::<AI>(enemy).copied() else {
                 continue;
+            };
+            // Shadowcasting is symmetric, so the enemy sees the player exactly when the player sees it.
+            let in_sight = world.map.is_visible(pos.x, pos.y);
+            let state = Self::next_state(&ai, hp, max_hp, distance, in_sight, world.resource_mut::<StdRng>());
+            if let Some(ai) = world.get_mut::<AI>(enemy) {
+                ai.state = state;
             }
-            let mut idx = 0;
-            for aggression_intent in &mut table.aggression_intents {                                
-                let strength = table.strengths
-                    .get(idx)
-                    .expect("This archetype should have strength");
-                let damage = Damage(rng.random_range(0..strength.0));
-                if let Some(aggro) = aggression_intent {
-                    to_damage.push((aggro.0, damage));
-                    *aggression_intent = None;
-                }
-                idx += 1;
-            }            
-        }        
-        for (entity, damage_received) in to_damage {                
-            for (key, table) in &mut world.tables {
-                if !key.has_hp {
-                    continue;
-                }
-                if let Some(idx) = table.entities.iter().position(|e| *e == entity) {
-                    if let Some(hp) = table.hitpoints.get_mut(idx) {
-                        hp.0 = hp.0.saturating_sub(damage_received.0);
-                    }
-                }
+            let steps: Vec<Position> = neighbours(&world.map, &pos)
+                .into_iter()
+                .filter(|step| world.blocking_at(step.x, step.y).is_none())
+                .collect();
+            let step = match state {
+                _ if ai.stationary => None,
+                _ if distance <= 1 && state != AIState::Fleeing => None,
+                // Other monsters aren't walls, but it's usually quicker to go around them.
+                AIState::Hunting => a_star_by(&world.map, &pos, &player_position, |step| {
+                    let cost = world.map.movement_cost(step.x, step.y)?;
+                    let crowded = world.blocking_at(step.x, step.y).is_some_and(|other| other != player);
+                    Some(if crowded { cost + 10 } else { cost })
+                })
+                    .and_then(|path| path.get(1).cloned()),
+                AIState::Fleeing => steps.into_iter()
+                    .filter(|step| manhattan_distance(step, &player_position) > distance)
+                    .max_by_key(|step| manhattan_distance(step, &player_position)),
+                AIState::Wandering if !steps.is_empty() => {
+                    let idx = world.resource_mut::<StdRng>().random_range(0..steps.len());
+                    Some(steps[idx].clone())
+                },
+                _ => None
+            };
+            match step {
+                Some(step) => world.insert(enemy, MovementIntent(step)),
+                // Attacking is just moving into the player.
+                None if distance <= 1 => world.insert(enemy, MovementIntent(player_position.clone())),
+                None => world.send_event(ActionTaken { entity: enemy, action: Action::Rest })
             }
-        }            
+        }
     }
 }
 
-pub struct DeathSystem;
-impl DeathSystem {
+// Moves the player between levels when they use the stairs they are standing on.
+pub struct StairsSystem;
+impl StairsSystem {
     pub fn run(world: &mut World) {
-        let mut to_remove: Vec<(ArchetypeKey, Entity)> = vec![];
-        for (key, table) in &mut world.tables {
-            if !key.has_hp {
+        let requests: Vec<(Entity, StairsDirection)> = world.events::<UseStairs>()
+            .iter_current()
+            .map(|request| (request.entity, request.direction))
+            .collect();
+        for (entity, direction) in requests {
+            let Some(pos) = world.get::<Position>(entity).cloned() else {
+                continue;
+            };
+            if world.map.tile_at(pos.x, pos.y) != Some(direction.tile()) {
+                world.resource_mut::<MessageLog>().push("There are no stairs here".to_string());
                 continue;
-            }            
-            for (idx, hp) in table.hitpoints.iter().enumerate() {
-                if hp.0 == 0 {
-                    to_remove.push((key.clone(), idx));
-                }                
             }
+            let message = match Dungeon::change_level(world, direction) {
+                Some(depth) => {
+                    world.send_event(ActionTaken { entity, action: Action::Move });
+                    format!("You reach depth {depth}")
+                },
+                None => "The way up is sealed".to_string()
+            };
+            world.resource_mut::<MessageLog>().push(message);
         }
-        for (key, idx) in to_remove.iter().rev() {
-            if let Some(table) = world.tables.get_mut(key) {
-                table.hitpoints.remove(*idx);
-                if key.has_position {
-                    table.positions.remove(*idx);
-                }
-                if key.has_strength {
-                    table.strengths.remove(*idx);
-                }       
-                table.aggression_intents.remove(*idx);         
+    }
+}
+
+pub struct EnergySystem;
+impl EnergySystem {
+    pub fn run(world: &mut World) {
+        let spent: Vec<(Entity, usize)> = world.events::<ActionTaken>()
+            .iter_current()
+            .map(|taken| (taken.entity, taken.action.cost()))
+            .collect();
+        for (entity, cost) in spent {
+            if let Some(energy) = world.get_mut::<Energy>(entity) {
+                energy.0 = energy.0.saturating_sub(cost);
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Advances game time until some actor has enough energy to act, then hands the
+// turn to the player if they are ready, otherwise to the enemies.
+pub struct TimeSystem;
+impl TimeSystem {
+    pub fn run(world: &mut World) {
+        if *world.resource::<TurnState>() == TurnState::GameOver
+            || world.query_ref::<&Speed, With<Energy>>().all(|(_, speed)| speed.0 == 0) {
+            return;
+        }
+        loop {
+            if world.query_ref::<&Energy, With<Player>>().any(|(_, energy)| energy.is_ready()) {
+                *world.resource_mut::<TurnState>() = TurnState::Player;
+                return;
+            }
+            if world.query_ref::<&Energy, Without<Player>>().any(|(_, energy)| energy.is_ready()) {
+                *world.resource_mut::<TurnState>() = TurnState::Enemy;
+                return;
+            }
+            for (_, (energy, speed)) in world.query::<(&mut Energy, &Speed), ()>() {
+                energy.0 += speed.0;
+            }
+            world.resource_mut::<TurnCounter>().0 += 1;
+        }
+    }
+}
+
+// Runs before `DespawnSystem` so the dead can still be named.
+pub struct LogSystem;
+impl LogSystem {
+    fn name(world: &World, entity: Entity) -> String {
+        if world.has::<Player>(entity) {
+            return "you".to_string();
+        }
+        match world.get::<MonsterKind>(entity) {
+            Some(kind) => format!("the {}", world.resource::<Raws>().monster(*kind).name),
+            None => format!("entity {entity}")
+        }
+    }
+
+    fn capitalise(message: String) -> String {
+        let mut chars = message.chars();
+        chars.next().map_or(message.clone(), |first| first.to_uppercase().chain(chars).collect())
+    }
+
+    fn describe(world: &World, outcome: &AttackOutcome) -> String {
+        let (attacker, defender) = (Self::name(world, outcome.attacker), Self::name(world, outcome.defender));
+        let verb = |you: &'static str, it: &'static str| if world.has::<Player>(outcome.attacker) { you } else { it };
+        let mut message = match outcome.kind {
+            HitKind::Miss => format!("{attacker} {} {defender}", verb("miss", "misses")),
+            HitKind::Hit => format!("{attacker} {} {defender} for {}", verb("hit", "hits"), outcome.damage),
+            HitKind::Critical => format!("{attacker} critically {} {defender} for {}", verb("hit", "hits"), outcome.damage)
+        };
+        if outcome.absorbed > 0 {
+            message.push_str(&format!(" ({} absorbed)", outcome.absorbed));
+        }
+        Self::capitalise(message)
+    }
+
+    pub fn run(world: &mut World) {
+        let mut messages: Vec<String> = world.events::<AttackOutcome>()
+            .iter_current()
+            .map(|outcome| Self::describe(world, outcome))
+            .collect();
+        for pickup in world.events::<GoldPickedUp>().iter_current() {
+            let picks = if world.has::<Player>(pickup.entity) { "pick" } else { "picks" };
+            let name = Self::name(world, pickup.entity);
+            messages.push(Self::capitalise(format!("{name} {picks} up {} gold ({} total)", pickup.value, pickup.total)));
+        }
+        for death in world.events::<EntityDied>().iter_current() {
+            let dies = if world.has::<Player>(death.entity) { "die" } else { "dies" };
+            messages.push(Self::capitalise(format!("{} {dies}", Self::name(world, death.entity))));
+        }
+        let log = world.resource_mut::<MessageLog>();
+        for message in messages {
+            log.push(message);
+        }
+    }
+}
+
+pub struct FovSystem;
+impl FovSystem {
+    pub fn run(world: &mut World) {
+        let Some(pos) = world.query_ref::<&Position, With<Player>>().next().map(|(_, pos)| pos.clone()) else {
+            return;
+        };
+        let radius = world.resource::<GameConfig>().fov_radius;
+        let visible = compute_fov(&world.map, pos.x, pos.y, radius);
+        world.map.clear_visible();
+        for (x, y) in visible {
+            world.map.set_visible(x, y);
+        }
+        // Nothing beyond the radius can be visible, so only look that far.
+        let in_view: Vec<Entity> = world.entities_in_radius(&pos, radius)
+            .into_iter()
+            .filter(|entity| world.get::<Position>(*entity).is_some_and(|pos| world.map.is_visible(pos.x, pos.y)))
+            .collect();
+        world.resource_mut::<InView>().0 = in_view;
+    }
+}
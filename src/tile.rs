@@ -0,0 +1,65 @@
+use crossterm::style::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tile {
+    Wall,
+    Floor,
+    Corridor,
+    DoorOpen,
+    DoorClosed,
+    StairsDown,
+    StairsUp,
+    // No generator places these yet.
+    #[allow(dead_code)]
+    Water,
+    #[allow(dead_code)]
+    Lava
+}
+impl Tile {
+    pub fn is_walkable(self) -> bool {
+        !matches!(self, Tile::Wall | Tile::DoorClosed)
+    }
+
+    // Closed doors can't be walked onto, but anyone can open them on the way.
+    pub fn is_passable(self) -> bool {
+        self != Tile::Wall
+    }
+
+    pub fn blocks_sight(self) -> bool {
+        matches!(self, Tile::Wall | Tile::DoorClosed)
+    }
+
+    pub fn movement_cost(self) -> Option<usize> {
+        match self {
+            Tile::Wall => None,
+            // One turn to open it and one to step through.
+            Tile::DoorClosed | Tile::Water => Some(2),
+            Tile::Lava => Some(10),
+            _ => Some(1)
+        }
+    }
+
+    pub fn glyph(self) -> char {
+        match self {
+            Tile::Wall => '#',
+            Tile::Floor | Tile::Corridor => '.',
+            Tile::DoorOpen => '\'',
+            Tile::DoorClosed => '+',
+            Tile::StairsDown => '>',
+            Tile::StairsUp => '<',
+            Tile::Water | Tile::Lava => '~'
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Tile::Wall => Color::Grey,
+            Tile::Floor => Color::White,
+            Tile::Corridor => Color::DarkYellow,
+            Tile::DoorOpen | Tile::DoorClosed => Color::Yellow,
+            Tile::StairsDown | Tile::StairsUp => Color::Cyan,
+            Tile::Water => Color::Blue,
+            Tile::Lava => Color::Red
+        }
+    }
+}
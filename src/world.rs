@@ -1,137 +1,606 @@
-use std::{collections::{HashMap, HashSet}, ops::AddAssign, vec};
+use std::{any::{type_name, Any, TypeId}, collections::HashMap, fmt::Display, ptr::NonNull};
 
-use rand::Rng;
+use rand::{rngs::StdRng, Rng};
 
-use crate::{components::{AggressionIntent, Position, Strength, HP}, game::TurnState, map::Map, systems::{AggressionSystem, DamageSystem, DeathSystem, InputSystem}};
+use crate::{components::{AIState, Armor, BlocksTile, Damage, Defense, Enemy, Energy, Gold, MaxHP, MonsterKind, Player, Position, Speed, Strength, Treasure, AI, HP}, dice::Dice, events::Events, map::Map, mapgen::Layout, pathfinding::manhattan_distance, query::{Access, QueryData, QueryFilter, QueryIter, ReadOnlyQueryData, With}, resources::Resources, raws::Raws, schedule::Schedule, spatial::SpatialIndex, spawn::SpawnTables, storage::{ArchetypeKey, Bundle, Column, DetachedRow, Table}, tile::Tile};
 
-pub type Entity = usize;
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Entity {
+    id: u32,
+    generation: u32
+}
+impl Display for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}v{}", self.id, self.generation)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct EntityLocation {
+    table: usize,
+    row: usize
+}
+
+struct EntitySlot {
+    generation: u32,
+    location: Option<EntityLocation>
+}
 
-#[derive(Eq, PartialEq, Hash, Clone)]
-pub struct ArchetypeKey {
-    pub has_position: bool,
-    pub is_controllable: bool,
-    pub is_enemy: bool,
-    pub has_hp: bool,
-    pub has_strength: bool    
+#[derive(Default)]
+struct Entities {
+    slots: Vec<EntitySlot>,
+    free: Vec<u32>
 }
+impl Entities {
+    fn alloc(&mut self, location: EntityLocation) -> Entity {
+        if let Some(id) = self.free.pop() {
+            let slot = &mut self.slots[id as usize];
+            slot.location = Some(location);
+            return Entity { id, generation: slot.generation };
+        }
+        self.slots.push(EntitySlot { generation: 0, location: Some(location) });
+        Entity { id: (self.slots.len() - 1) as u32, generation: 0 }
+    }
 
-pub struct Table {
-    pub key: ArchetypeKey,
-    pub entities: Vec<Entity>,
-    pub positions: Vec<Position>,
-    pub hitpoints: Vec<HP>,
-    pub aggression_intents: Vec<Option<AggressionIntent>>,
-    pub strengths: Vec<Strength>
+    // Bumps the generation so every outstanding handle to `entity` goes stale.
+    fn free(&mut self, entity: Entity) -> Option<EntityLocation> {
+        let location = self.location(entity)?;
+        let slot = &mut self.slots[entity.id as usize];
+        slot.generation += 1;
+        slot.location = None;
+        self.free.push(entity.id);
+        Some(location)
+    }
+
+    fn location(&self, entity: Entity) -> Option<EntityLocation> {
+        self.slots.get(entity.id as usize)
+            .filter(|slot| slot.generation == entity.generation)
+            .and_then(|slot| slot.location)
+    }
+
+    fn set_location(&mut self, entity: Entity, location: EntityLocation) {
+        if let Some(slot) = self.slots.get_mut(entity.id as usize) {
+            slot.location = Some(location);
+        }
+    }
 }
 
 pub struct World {
-    next_entity: Entity,
+    entities: Entities,
     pub map: Map,
-    pub tables: HashMap<ArchetypeKey, Table>,
-    pub turn_state: TurnState
+    tables: Vec<Table>,
+    table_index: HashMap<ArchetypeKey, usize>,
+    resources: Resources,
+    event_updaters: Vec<fn(&mut Resources)>,
+    spatial: SpatialIndex,
+    pub schedule: Schedule
 }
 impl World {
     pub fn new(map: Map) -> Self {
-        let mut rng = rand::rng();
-        let turn_state = if rng.random_bool(0.5) { TurnState::Enemy } else { TurnState::Player };
-        Self { next_entity: 0, map, tables: HashMap::new(), turn_state }
+        Self { 
+            entities: Entities::default(), 
+            map, 
+            tables: vec![], 
+            table_index: HashMap::new(), 
+            resources: Resources::default(),
+            event_updaters: vec![],
+            spatial: SpatialIndex::default(),
+            schedule: Schedule::default()
+        }
     }
 
-    fn get_next_entity(&mut self) -> Entity {
-        self.next_entity += 1;
-        self.next_entity - 1
+    pub fn update(&mut self) {
+        for update in &self.event_updaters {
+            update(&mut self.resources);
+        }
+        let mut schedule = std::mem::take(&mut self.schedule);
+        schedule.run(self);
+        self.schedule = schedule;
     }
 
-    pub fn update(&mut self) {
-        if InputSystem::run(self) {
-            AggressionSystem::run(self);
-            DamageSystem::run(self);
-            DeathSystem::run(self);
-        }                       
+    pub fn insert_resource<R: 'static>(&mut self, resource: R) {
+        self.resources.insert(resource);
     }
 
-    pub fn initialize(&mut self) {
+    pub fn get_resource<R: 'static>(&self) -> Option<&R> {
+        self.resources.get::<R>()
+    }
+
+    pub fn resource<R: 'static>(&self) -> &R {
+        self.resources.get::<R>()
+            .unwrap_or_else(|| panic!("Resource {} should be inserted", type_name::<R>()))
+    }
+
+    pub fn resource_mut<R: 'static>(&mut self) -> &mut R {
+        self.resources.get_mut::<R>()
+            .unwrap_or_else(|| panic!("Resource {} should be inserted", type_name::<R>()))
+    }
+
+    pub fn add_event<E: 'static>(&mut self) {
+        if self.get_resource::<Events<E>>().is_some() {
+            return;
+        }
+        self.insert_resource(Events::<E>::default());
+        self.event_updaters.push(|resources| {
+            if let Some(events) = resources.get_mut::<Events<E>>() {
+                events.update();
+            }
+        });
+    }
+
+    pub fn events<E: 'static>(&self) -> &Events<E> {
+        self.resource::<Events<E>>()
+    }
+
+    pub fn send_event<E: 'static>(&mut self, event: E) {
+        self.resource_mut::<Events<E>>().send(event);
+    }
+
+    pub fn initialize(&mut self, layout: &Layout, rng: &mut StdRng) {
         self.spawn_player();
-        self.spawn_enemy();
+        self.populate_level(0, layout, rng);
     }
 
-    pub fn spawn_player(&mut self) -> Entity {
-        let key = ArchetypeKey { 
-            is_controllable: true, 
-            has_position: true,        
-            is_enemy: false,
-            has_hp: true,
-            has_strength: true            
+    // Fills a freshly generated level from the `SpawnTables`: every region rolls
+    // how many monsters it gets and which, except the one the player stands in.
+    // Prefab markers are filled on top of that. Call it once the player has
+    // been placed on the level, with the RNG the level was generated from.
+    pub fn populate_level(&mut self, depth: usize, layout: &Layout, rng: &mut StdRng) {
+        let tables = self.resource::<SpawnTables>().clone();
+        let players: Vec<Position> = self.query_ref::<&Position, With<Player>>()
+            .map(|(_, pos)| pos.clone())
+            .collect();
+        for position in &layout.monsters {
+            if self.blocking_at(position.x, position.y).is_some() {
+                continue;
+            }
+            if let Some(kind) = tables.monsters.roll(depth, rng) {
+                self.spawn_monster(kind, position.clone(), rng);
+            }
+        }
+        for position in &layout.treasure {
+            let value = rng.random_range(5..=20) * (depth + 1);
+            self.spawn((position.clone(), Treasure(value)));
+        }
+        for region in &layout.regions {
+            let starts_here = region.tiles.iter()
+                .any(|tile| players.iter().any(|player| manhattan_distance(tile, player) <= 1));
+            if starts_here {
+                continue;
+            }
+            let mut free: Vec<Position> = region.tiles.iter()
+                .filter(|tile| self.map.is_walkable(tile.x, tile.y) && self.blocking_at(tile.x, tile.y).is_none())
+                .cloned()
+                .collect();
+            let count = tables.per_room.roll(depth, rng).unwrap_or(0);
+            for _ in 0..count {
+                if free.is_empty() {
+                    break;
+                }
+                let position = free.swap_remove(rng.random_range(0..free.len()));
+                let Some(kind) = tables.monsters.roll(depth, rng) else {
+                    break;
+                };
+                self.spawn_monster(kind, position, rng);
+            }
+        }
+    }
+
+    pub fn query<Q: QueryData, F: QueryFilter>(&mut self) -> QueryIter<'_, Q, F> {
+        let mut access = Access::default();
+        Q::access(&mut access);
+        assert!(!access.writes::<Position>(), "Position should only be changed through World::insert, which keeps the spatial index up to date");
+        // SAFETY: `self` is borrowed mutably for as long as the iterator lives.
+        unsafe { QueryIter::new(self.tables.iter_mut().map(NonNull::from)) }
+    }
+
+    pub fn query_ref<Q: ReadOnlyQueryData, F: QueryFilter>(&self) -> QueryIter<'_, Q, F> {
+        // SAFETY: `Q` is read-only and `self` is borrowed for as long as the iterator lives.
+        unsafe { QueryIter::new(self.tables.iter().map(NonNull::from)) }
+    }
+
+    fn table_for(&mut self, key: ArchetypeKey, create: impl FnOnce() -> Table) -> usize {
+        if let Some(idx) = self.table_index.get(&key) {
+            return *idx;
+        }
+        self.tables.push(create());
+        self.table_index.insert(key, self.tables.len() - 1);
+        self.tables.len() - 1
+    }
+
+    fn relocate(&mut self, table: usize, swapped: Option<Entity>, row: usize) {
+        if let Some(entity) = swapped {
+            self.entities.set_location(entity, EntityLocation { table, row });
+        }
+    }
+
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let types = B::type_ids();
+        let key = ArchetypeKey::new(types.clone());
+        assert_eq!(key.types().len(), types.len(), "Bundle {} should not repeat component types", type_name::<B>());
+        let table_idx = self.table_for(key.clone(), || Table::new(key, B::empty_columns()));
+        let table = &mut self.tables[table_idx];
+        let entity = self.entities.alloc(EntityLocation { table: table_idx, row: table.len() });
+        table.push_entity(entity);
+        bundle.push_into(table);
+        self.reindex(entity, None);
+        entity
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.location(entity).is_some()
+    }
+
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if let Some(pos) = self.get::<Position>(entity).cloned() {
+            self.spatial.remove(entity, &pos);
+        }
+        let Some(location) = self.entities.free(entity) else {
+            return false;
         };
-        let id = self.get_next_entity();
-        let table = self.tables.entry(key.clone())
-            .or_insert_with(|| Table {
-                key,
-                entities: vec![],
-                positions: vec![],
-                hitpoints: vec![],
-                aggression_intents: vec![],
-                strengths: vec![]
-            });            
-        let position = self.map.get_tiles()
+        let swapped = self.tables[location.table].remove_row(location.row);
+        self.relocate(location.table, swapped, location.row);
+        true
+    }
+
+    // Removes the entity from the world but keeps its components, so it can be
+    // brought back with `attach` (under a new handle) when its level is reloaded.
+    pub fn detach(&mut self, entity: Entity) -> Option<DetachedRow> {
+        if let Some(pos) = self.get::<Position>(entity).cloned() {
+            self.spatial.remove(entity, &pos);
+        }
+        let location = self.entities.free(entity)?;
+        let (row, swapped) = self.tables[location.table].detach_row(location.row);
+        self.relocate(location.table, swapped, location.row);
+        Some(row)
+    }
+
+    pub fn attach(&mut self, row: DetachedRow) -> Entity {
+        let key = row.key().clone();
+        let table_idx = self.table_for(key.clone(), || Table::new(key, row.empty_columns()));
+        let table = &mut self.tables[table_idx];
+        let entity = self.entities.alloc(EntityLocation { table: table_idx, row: table.len() });
+        table.attach_row(entity, row);
+        self.reindex(entity, None);
+        entity
+    }
+
+    fn tables_pair_mut(&mut self, a: usize, b: usize) -> (&mut Table, &mut Table) {
+        if a < b {
+            let (left, right) = self.tables.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.tables.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+
+    fn target_table(&mut self, source: usize, key: ArchetypeKey, add_column: impl FnOnce(&mut HashMap<TypeId, Box<dyn Column>>)) -> usize {
+        let mut columns = self.tables[source].empty_columns();
+        self.table_for(key.clone(), || {
+            add_column(&mut columns);
+            Table::new(key, columns)
+        })
+    }
+
+    fn finish_move(&mut self, entity: Entity, from: EntityLocation, swapped: Option<Entity>, target: usize) {
+        self.relocate(from.table, swapped, from.row);
+        let row = self.tables[target].len() - 1;
+        self.entities.set_location(entity, EntityLocation { table: target, row });
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) {
+        let location = self.entities.location(entity)
+            .unwrap_or_else(|| panic!("Entity {entity} should be alive"));
+        let moved_from = if TypeId::of::<T>() == TypeId::of::<Position>() {
+            self.get::<Position>(entity).cloned()
+        } else {
+            None
+        };
+        if let Some(column) = self.tables[location.table].column_mut::<T>() {
+            column[location.row] = component;
+            if TypeId::of::<T>() == TypeId::of::<Position>() {
+                self.reindex(entity, moved_from);
+            }
+            return;
+        }
+        let key = self.tables[location.table].key().with(TypeId::of::<T>());
+        let target_idx = self.target_table(location.table, key, |columns| {
+            columns.insert(TypeId::of::<T>(), Box::new(Vec::<T>::new()));
+        });
+        let (source, target) = self.tables_pair_mut(location.table, target_idx);
+        let swapped = source.move_row(location.row, target);
+        target.push(component);
+        self.finish_move(entity, location, swapped, target_idx);
+        if TypeId::of::<T>() == TypeId::of::<Position>() {
+            self.reindex(entity, None);
+        }
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        let location = self.entities.location(entity)?;
+        if !self.tables[location.table].has::<T>() {
+            return None;
+        }
+        let key = self.tables[location.table].key().without(TypeId::of::<T>());
+        let target_idx = self.target_table(location.table, key, |_| ());
+        let (source, target) = self.tables_pair_mut(location.table, target_idx);
+        let (value, swapped) = source.take_row::<T>(location.row, target);
+        self.finish_move(entity, location, swapped, target_idx);
+        if let Some(pos) = (&value as &dyn Any).downcast_ref::<Position>() {
+            self.spatial.remove(entity, pos);
+        }
+        Some(value)
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        let location = self.entities.location(entity)?;
+        self.tables[location.table].column::<T>()?.get(location.row)
+    }
+
+    pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+        self.entities.location(entity)
+            .is_some_and(|location| self.tables[location.table].has::<T>())
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        assert_ne!(TypeId::of::<T>(), TypeId::of::<Position>(), "Position should only be changed through World::insert, which keeps the spatial index up to date");
+        let location = self.entities.location(entity)?;
+        self.tables[location.table].column_mut::<T>()?.get_mut(location.row)
+    }
+
+    // Moves `entity` in the spatial index from `from` to wherever its `Position`
+    // now says, if it has one.
+    fn reindex(&mut self, entity: Entity, from: Option<Position>) {
+        if let Some(from) = from {
+            self.spatial.remove(entity, &from);
+        }
+        if let Some(pos) = self.get::<Position>(entity).cloned() {
+            self.spatial.insert(entity, &pos);
+        }
+    }
+
+    pub fn entities_at(&self, x: usize, y: usize) -> &[Entity] {
+        self.spatial.at(x, y)
+    }
+
+    // The entity on `(x, y)` that stops anything else from standing there.
+    pub fn blocking_at(&self, x: usize, y: usize) -> Option<Entity> {
+        self.entities_at(x, y)
+            .iter()
+            .copied()
+            .find(|entity| self.has::<BlocksTile>(*entity))
+    }
+
+    // Every entity within `radius` of `centre`, measured the same way as the field of view.
+    pub fn entities_in_radius(&self, centre: &Position, radius: usize) -> Vec<Entity> {
+        let (min_x, min_y) = (centre.x.saturating_sub(radius), centre.y.saturating_sub(radius));
+        let mut found = vec![];
+        for y in min_y..=centre.y + radius {
+            for x in min_x..=centre.x + radius {
+                let (dx, dy) = (x.abs_diff(centre.x), y.abs_diff(centre.y));
+                if dx * dx + dy * dy <= radius * radius {
+                    found.extend_from_slice(self.entities_at(x, y));
+                }
+            }
+        }
+        found
+    }
+
+    fn first_floor_tile(&self) -> Position {
+        self.map.get_tiles()
             .iter()
             .enumerate()
-            .find_map(|(idx, ch)| {
-                if *ch == '.' {
+            .find_map(|(idx, tile)| {
+                if *tile == Tile::Floor {
                     let (y, x) = self.map.idx_xy(idx);
                     Some(Position::new(x, y))
                 } else {
                     None
                 }
             })
-            .expect("Should already exist a carved room");            
-        let mut rng = rand::rng();
-        let hp = HP(rng.random_range(0..10));
-        table.entities.push(id);
-        table.positions.push(position);
-        table.hitpoints.push(hp);
-        table.aggression_intents.push(None);
-        table.strengths.push(Strength(rng.random_range(1..6)));
-        id
-    }
-
-    pub fn spawn_enemy(&mut self) -> Entity {
-        let key = ArchetypeKey {
-            has_position: true,
-            is_controllable: false,
-            is_enemy: true,
-            has_hp: true,
-            has_strength: true            
+            .expect("Should already exist a carved room")
+    }
+
+    pub fn spawn_player(&mut self) -> Entity {
+        let position = self.map.find_tile(Tile::StairsUp)
+            .map_or_else(|| self.first_floor_tile(), |(x, y)| Position::new(x, y));
+        let rng = self.resource_mut::<StdRng>();
+        let hp = Dice { count: 2, sides: 6, bonus: 10 }.roll(rng);
+        let strength = Strength(rng.random_range(1..=4));
+        let energy = Energy(rng.random_range(0..Energy::THRESHOLD));
+        let damage = Damage(Dice { count: 1, sides: 6, bonus: 0 });
+        self.spawn((Player, BlocksTile, position, HP(hp), MaxHP(hp), strength, damage, Defense(12), Armor(1), Speed(100), energy, Gold(0)))
+    }
+
+    // Spawns an enemy as its entry in the monster raws describes it.
+    pub fn spawn_monster(&mut self, kind: MonsterKind, position: Position, rng: &mut StdRng) -> Entity {
+        let monster = self.resource::<Raws>().monster(kind).clone();
+        let hp = monster.hp.roll(rng).max(1);
+        let energy = Energy(rng.random_range(0..Energy::THRESHOLD));
+        let ai = AI {
+            state: if monster.awake { AIState::Wandering } else { AIState::Idle },
+            sight_radius: monster.sight,
+            behaviour: monster.behaviour,
+            stationary: monster.stationary
         };
-        let id = self.get_next_entity();
-        let table = self.tables.entry(key.clone())
-            .or_insert_with(|| Table { 
-                key, 
-                entities: vec![], 
-                positions: vec![],
-                hitpoints: vec![],
-                aggression_intents: vec![],
-                strengths: vec![]
-            });
-        let position = self.map.get_tiles()
-            .iter()
+        let (strength, damage) = (Strength(monster.strength), Damage(monster.damage));
+        let (defense, armor) = (Defense(monster.defense), Armor(monster.armor));
+        self.spawn((Enemy, BlocksTile, kind, position, HP(hp), MaxHP(hp), strength, damage, defense, armor, Speed(monster.speed), energy, ai))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Id(usize);
+
+    #[derive(Debug, PartialEq)]
+    struct Label(&'static str);
+
+    struct Marker;
+
+    const LABELS: [&str; 4] = ["a", "b", "c", "d"];
+
+    // Four entities sharing one table, in spawn order.
+    fn world() -> (World, Vec<Entity>) {
+        let mut world = World::new(Map::new(4, 4));
+        let entities = LABELS.iter()
             .enumerate()
-            .find_map(|(idx, ch)| {
-                if *ch == '.' {
-                    let (y, x) = self.map.idx_xy(idx);
-                    Some(Position::new(x + 2, y + 2))
-                } else {
-                    None
-                }
-            })
-            .expect("Should already exist a carved room");  
-        let mut rng = rand::rng();
-        let hp = HP(rng.random_range(0..6));
-        table.entities.push(id);
-        table.positions.push(position);
-        table.hitpoints.push(hp);
-        table.aggression_intents.push(None);
-        table.strengths.push(Strength(rng.random_range(1..3)));
-        id        
-    }
-}
\ No newline at end of file
+            .map(|(idx, label)| world.spawn((Id(idx), Label(label))))
+            .collect();
+        (world, entities)
+    }
+
+    // Checks the entities at `idxs` still hold the components they were spawned with.
+    fn assert_intact(world: &World, entities: &[Entity], idxs: impl IntoIterator<Item = usize>) {
+        for idx in idxs {
+            let entity = &entities[idx];
+            assert_eq!(world.get::<Id>(*entity), Some(&Id(idx)), "{entity} lost its Id");
+            assert_eq!(world.get::<Label>(*entity), Some(&Label(LABELS[idx])), "{entity} lost its Label");
+        }
+    }
+
+    #[test]
+    fn inserting_on_first_and_last_rows_keeps_every_row() {
+        let (mut world, entities) = world();
+        world.insert(entities[0], Marker);
+        world.insert(entities[3], Marker);
+        assert_intact(&world, &entities, 0..4);
+        let marked: Vec<bool> = entities.iter().map(|entity| world.has::<Marker>(*entity)).collect();
+        assert_eq!(marked, vec![true, false, false, true]);
+    }
+
+    #[test]
+    fn removing_from_first_and_last_rows_keeps_every_row() {
+        let (mut world, entities) = world();
+        for entity in &entities {
+            world.insert(*entity, Marker);
+        }
+        assert!(world.remove::<Marker>(entities[0]).is_some());
+        assert!(world.remove::<Marker>(entities[3]).is_some());
+        assert!(world.remove::<Marker>(entities[3]).is_none());
+        assert_intact(&world, &entities, 0..4);
+        let marked: Vec<bool> = entities.iter().map(|entity| world.has::<Marker>(*entity)).collect();
+        assert_eq!(marked, vec![false, true, true, false]);
+        assert_eq!(world.remove::<Label>(entities[1]), Some(Label("b")));
+        assert_eq!(world.get::<Id>(entities[1]), Some(&Id(1)));
+        assert_intact(&world, &entities, [0, 2, 3]);
+    }
+
+    #[test]
+    fn despawning_first_and_last_rows_keeps_the_others() {
+        let (mut world, entities) = world();
+        assert!(world.despawn(entities[0]));
+        assert!(world.despawn(entities[3]));
+        assert_intact(&world, &entities, 1..3);
+    }
+
+    #[test]
+    fn stale_handles_see_nothing() {
+        let (mut world, entities) = world();
+        assert!(world.despawn(entities[1]));
+        assert!(!world.is_alive(entities[1]));
+        assert_eq!(world.get::<Id>(entities[1]), None);
+        assert!(!world.despawn(entities[1]));
+        // The freed slot is reused under a new generation.
+        let reused = world.spawn((Id(9), Label("z")));
+        assert_ne!(reused, entities[1]);
+        assert_eq!(world.get::<Id>(entities[1]), None);
+        assert_eq!(world.get::<Id>(reused), Some(&Id(9)));
+    }
+
+    #[test]
+    fn detached_rows_come_back_whole() {
+        let (mut world, entities) = world();
+        let row = world.detach(entities[0]).expect("Entity should be alive");
+        assert_eq!(world.get::<Id>(entities[0]), None);
+        let back = world.attach(row);
+        assert_eq!(world.get::<Id>(back), Some(&Id(0)));
+        assert_eq!(world.get::<Label>(back), Some(&Label("a")));
+        assert_intact(&world, &entities, 1..4);
+    }
+
+    // Two entities sharing (1, 1), only the first blocking it, and a third at (5, 5).
+    fn spatial_world() -> (World, [Entity; 3]) {
+        let mut world = World::new(Map::new(8, 8));
+        let entities = [
+            world.spawn((Position::new(1, 1), BlocksTile)),
+            world.spawn((Position::new(1, 1), Label("item"))),
+            world.spawn((Position::new(5, 5), BlocksTile))
+        ];
+        (world, entities)
+    }
+
+    #[test]
+    fn spawning_indexes_positions() {
+        let (world, [a, b, c]) = spatial_world();
+        assert_eq!(world.entities_at(1, 1).len(), 2);
+        assert!(world.entities_at(1, 1).contains(&a) && world.entities_at(1, 1).contains(&b));
+        assert_eq!(world.blocking_at(1, 1), Some(a));
+        assert_eq!(world.entities_at(5, 5), &[c]);
+        assert_eq!(world.entities_in_radius(&Position::new(3, 3), 2).len(), 0);
+        assert_eq!(world.entities_in_radius(&Position::new(3, 3), 3).len(), 3);
+    }
+
+    #[test]
+    fn inserting_a_position_moves_the_entity_in_the_index() {
+        let (mut world, [a, b, _]) = spatial_world();
+        world.insert(a, Position::new(3, 1));
+        assert_eq!(world.entities_at(1, 1), &[b]);
+        assert_eq!(world.blocking_at(1, 1), None);
+        assert_eq!(world.entities_at(3, 1), &[a]);
+        assert_eq!(world.blocking_at(3, 1), Some(a));
+        assert_eq!(world.entities_in_radius(&Position::new(3, 1), 1), vec![a]);
+        // Giving a position to an entity without one indexes it too.
+        let d = world.spawn((Label("new"),));
+        world.insert(d, Position::new(7, 7));
+        assert_eq!(world.entities_at(7, 7), &[d]);
+    }
+
+    #[test]
+    fn removing_a_position_drops_the_entity_from_the_index() {
+        let (mut world, [a, b, _]) = spatial_world();
+        assert!(world.remove::<Position>(a).is_some());
+        assert_eq!(world.entities_at(1, 1), &[b]);
+        assert_eq!(world.blocking_at(1, 1), None);
+        assert_eq!(world.entities_in_radius(&Position::new(1, 1), 1), vec![b]);
+        // Dropping something else leaves the index alone.
+        assert!(world.remove::<Label>(b).is_some());
+        assert_eq!(world.entities_at(1, 1), &[b]);
+    }
+
+    #[test]
+    fn despawning_drops_the_entity_from_the_index() {
+        let (mut world, [a, b, c]) = spatial_world();
+        assert!(world.despawn(c));
+        assert!(world.entities_at(5, 5).is_empty());
+        assert_eq!(world.blocking_at(5, 5), None);
+        assert!(world.entities_in_radius(&Position::new(5, 5), 2).is_empty());
+        assert!(world.despawn(a));
+        assert_eq!(world.entities_at(1, 1), &[b]);
+        assert_eq!(world.blocking_at(1, 1), None);
+    }
+
+    #[test]
+    fn detaching_and_attaching_update_the_index() {
+        let (mut world, [a, b, _]) = spatial_world();
+        let row = world.detach(a).expect("Entity should be alive");
+        assert_eq!(world.entities_at(1, 1), &[b]);
+        assert_eq!(world.blocking_at(1, 1), None);
+        let back = world.attach(row);
+        assert_eq!(world.entities_at(1, 1).len(), 2);
+        assert!(world.entities_at(1, 1).contains(&back));
+        assert_eq!(world.blocking_at(1, 1), Some(back));
+        assert_eq!(world.entities_in_radius(&Position::new(1, 1), 0).len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Position should only be changed through World::insert")]
+    fn writing_a_position_through_get_mut_panics() {
+        let (mut world, [a, ..]) = spatial_world();
+        world.get_mut::<Position>(a);
+    }
+}